@@ -10,6 +10,7 @@ use tokio::time::Duration;
 use futures::{stream, StreamExt};
 
 use crate::config::Config;
+use crate::http;
 use crate::lang::LanguageManager;
 use crate::ui::progress::ASNProgressTracker;
 
@@ -22,10 +23,10 @@ pub struct ASNScanner<'a> {
 
 impl<'a> ASNScanner<'a> {
     pub fn new(lang: &'a LanguageManager, config: &'a Config) -> Self {
-        let client = Client::builder()
-            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
-            .timeout(Duration::from_secs(config.asn.api_timeout as u64))
-            .build()
+        let client = http::profile_builder(&config.probe)
+            .and_then(|builder| Ok(builder
+                .timeout(Duration::from_secs(config.asn.api_timeout as u64))
+                .build()?))
             .unwrap_or_default();
             
         Self {
@@ -0,0 +1,490 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context, Result};
+use reqwest::Url;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::channel::{self, Channel};
+use crate::config::OutputConfig;
+use crate::m3u::{self, Document, Header};
+
+/// `{group}` of channels without a group
+const NO_GROUP: &str = "ungrouped";
+
+/// `{country}` of channels without `tvg-country`
+const NO_COUNTRY: &str = "unknown";
+
+/// Appends waiting for the catalog writer before senders have to wait
+const WRITER_QUEUE: usize = 64;
+
+/// Every source of one logical channel, across servers
+#[derive(Debug, Clone)]
+pub struct ChannelGroup {
+    /// Canonical key shared by the sources
+    pub key: String,
+    /// Name of the first source
+    pub name: String,
+    pub sources: Vec<Channel>,
+}
+
+impl ChannelGroup {
+    /// Sources whose last check found them playing
+    pub fn working(&self) -> usize {
+        self.sources.iter().filter(|source| source.is_working() == Some(true)).count()
+    }
+
+    /// Distinct servers the sources come from
+    pub fn servers(&self) -> usize {
+        self.sources.iter()
+            .filter_map(|source| source.server.as_deref())
+            .collect::<HashSet<_>>()
+            .len()
+    }
+}
+
+/// Group channels by their canonical key, in order of first appearance.
+///
+/// A channel without `tvg-id` joins the group of a channel that has one if
+/// their names normalize to the same text.
+pub fn group(channels: Vec<Channel>) -> Vec<ChannelGroup> {
+    let mut id_by_name = HashMap::new();
+    for channel in &channels {
+        let key = channel.canonical_key();
+        if key.starts_with("id:") {
+            id_by_name.entry(channel::normalize_name(&channel.name)).or_insert(key);
+        }
+    }
+
+    let mut groups: Vec<ChannelGroup> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for channel in channels {
+        let mut key = channel.canonical_key();
+        if !key.starts_with("id:") {
+            if let Some(id_key) = id_by_name.get(&channel::normalize_name(&channel.name)) {
+                key = id_key.clone();
+            }
+        }
+
+        match index.get(&key) {
+            Some(&position) => groups[position].sources.push(channel),
+            None => {
+                index.insert(key.clone(), groups.len());
+                groups.push(ChannelGroup {
+                    key,
+                    name: channel.name.clone(),
+                    sources: vec![channel],
+                });
+            }
+        }
+    }
+
+    groups
+}
+
+/// Path of the playlist holding every working channel
+pub fn combined_path(config: &OutputConfig) -> PathBuf {
+    Path::new(&config.dir).join(&config.combined)
+}
+
+/// Handle to the task that writes the output playlists.
+///
+/// Every playlist task sends its working channels here instead of writing
+/// itself, so appends never interleave, and the URLs already in the combined
+/// playlist are read once instead of on every append.
+#[derive(Clone)]
+pub struct CatalogWriter {
+    sender: mpsc::Sender<Request>,
+}
+
+enum Request {
+    Append {
+        channels: Vec<Channel>,
+        reply: oneshot::Sender<Result<usize>>,
+    },
+    /// Answered once every request sent before it is done
+    Flush {
+        reply: oneshot::Sender<()>,
+    },
+}
+
+impl CatalogWriter {
+    /// Index the combined playlist and start the writer on a thread of its
+    /// own, since it blocks on file writes. It runs until every handle is
+    /// dropped.
+    pub fn spawn(config: OutputConfig) -> Result<Self> {
+        let mut catalog = Catalog::open(config)?;
+        let (sender, mut receiver) = mpsc::channel::<Request>(WRITER_QUEUE);
+
+        std::thread::Builder::new().name("catalog-writer".to_string()).spawn(move || {
+            while let Some(request) = receiver.blocking_recv() {
+                // The sender may have stopped waiting; the channels are written anyway
+                match request {
+                    Request::Append { channels, reply } => {
+                        let _ = reply.send(catalog.append(&channels));
+                    }
+                    Request::Flush { reply } => {
+                        let _ = reply.send(());
+                    }
+                }
+            }
+        })?;
+
+        Ok(Self { sender })
+    }
+
+    /// Append the channels that are not in the combined playlist yet to it
+    /// and to their split playlists.
+    ///
+    /// Returns how many channels were new.
+    pub async fn append(&self, channels: Vec<Channel>) -> Result<usize> {
+        let (reply, response) = oneshot::channel();
+        self.sender.send(Request::Append { channels, reply }).await
+            .map_err(|_| anyhow!("Catalog writer has stopped"))?;
+        response.await.map_err(|_| anyhow!("Catalog writer has stopped"))?
+    }
+
+    /// Wait until every append sent so far, by any handle, is written
+    pub async fn flush(&self) -> Result<()> {
+        let (reply, response) = oneshot::channel();
+        self.sender.send(Request::Flush { reply }).await
+            .map_err(|_| anyhow!("Catalog writer has stopped"))?;
+        response.await.map_err(|_| anyhow!("Catalog writer has stopped"))
+    }
+}
+
+/// The output playlists as the writer task keeps them
+struct Catalog {
+    config: OutputConfig,
+    header: Header,
+    /// URLs in each playlist, by file relative to the output directory.
+    /// A file is read the first time it is appended to.
+    known: HashMap<String, HashSet<String>>,
+}
+
+impl Catalog {
+    fn open(config: OutputConfig) -> Result<Self> {
+        let mut catalog = Self {
+            header: header(&config),
+            config,
+            known: HashMap::new(),
+        };
+
+        // Read up front, so a combined playlist that cannot be read stops the scan early
+        let combined = catalog.config.combined.clone();
+        catalog.urls(&combined)?;
+
+        Ok(catalog)
+    }
+
+    /// URLs already in a playlist
+    fn urls(&mut self, file: &str) -> Result<&mut HashSet<String>> {
+        if !self.known.contains_key(file) {
+            let path = Path::new(&self.config.dir).join(file);
+            let mut urls = HashSet::new();
+            if path.exists() {
+                urls.extend(m3u::parse_file(&path)?.uris().map(str::to_string));
+            }
+            self.known.insert(file.to_string(), urls);
+        }
+        Ok(self.known.get_mut(file).expect("indexed above"))
+    }
+
+    /// Append each channel to the playlists it belongs in and is not in yet.
+    ///
+    /// Every playlist is recorded as soon as it is written, so when one
+    /// fails, the next append only retries that one. Returns how many
+    /// channels were new to the combined playlist.
+    fn append(&mut self, channels: &[Channel]) -> Result<usize> {
+        let mut failure = None;
+        let mut files: BTreeMap<String, Vec<&Channel>> = BTreeMap::new();
+        let mut queued = HashSet::new();
+        for channel in channels {
+            for file in files_for(channel, &self.config) {
+                let known = match self.urls(&file) {
+                    Ok(known) => known.contains(&channel.url),
+                    Err(e) => {
+                        // Unreadable: left out of this append, like a failed write
+                        failure.get_or_insert(e);
+                        continue;
+                    }
+                };
+                if known || !queued.insert((file.clone(), channel.url.as_str())) {
+                    continue;
+                }
+                files.entry(file).or_default().push(channel);
+            }
+        }
+
+        let new_channels = files.get(&self.config.combined).map_or(0, Vec::len);
+
+        for (file, channels) in files {
+            match append_entries(&Path::new(&self.config.dir).join(&file), &self.header, &channels) {
+                Ok(()) => self.urls(&file)?.extend(channels.iter().map(|channel| channel.url.clone())),
+                Err(e) => {
+                    failure.get_or_insert(e);
+                }
+            }
+        }
+
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(new_channels),
+        }
+    }
+}
+
+/// Write `channels` as new playlists under `dir`, named after `config`'s
+/// templates. Returns the files written.
+pub fn write(channels: &[Channel], dir: &Path, config: &OutputConfig) -> Result<Vec<PathBuf>> {
+    let channels: Vec<&Channel> = channels.iter().collect();
+
+    let mut written = Vec::new();
+    for (file, channels) in split(&channels, config) {
+        let path = dir.join(file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let document = Document {
+            header: Some(header(config)),
+            entries: channels.iter().map(|channel| channel.to_entry()).collect(),
+            warnings: Vec::new(),
+        };
+        write_atomic(&path, &document.to_string())?;
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+/// `#EXTM3U` header of the playlists, pointing to the guide if one is set
+fn header(config: &OutputConfig) -> Header {
+    let mut header = Header::default();
+    if let Some(tvg_url) = config.tvg_url.as_deref().map(str::trim).filter(|url| !url.is_empty()) {
+        header.attributes.set("x-tvg-url", tvg_url);
+    }
+    header
+}
+
+/// Channels by the file they go in, relative to the output directory
+fn split<'a>(channels: &[&'a Channel], config: &OutputConfig) -> BTreeMap<String, Vec<&'a Channel>> {
+    let mut files: BTreeMap<String, Vec<&Channel>> = BTreeMap::new();
+    for channel in channels {
+        for file in files_for(channel, config) {
+            files.entry(file).or_default().push(channel);
+        }
+    }
+    files
+}
+
+/// The combined playlist and every split playlist `channel` belongs in
+fn files_for(channel: &Channel, config: &OutputConfig) -> Vec<String> {
+    let server = channel.server.as_deref().and_then(|server| Url::parse(server).ok());
+    let host = server.as_ref().and_then(|url| url.host_str()).unwrap_or_default();
+    let port = server.as_ref()
+        .and_then(|url| url.port_or_known_default())
+        .map(|port| port.to_string())
+        .unwrap_or_default();
+    let countries = channel.countries();
+
+    let values = |country: &str| [
+        ("server", format!("{}_{}", host, port)),
+        ("host", host.to_string()),
+        ("port", port.clone()),
+        ("group", channel.group.clone().unwrap_or_else(|| NO_GROUP.to_string())),
+        ("country", country.to_string()),
+    ];
+    let first_country = countries.first().copied().unwrap_or(NO_COUNTRY);
+
+    let mut files = vec![config.combined.clone()];
+    if let Some(template) = config.per_server.as_deref().filter(|_| server.is_some()) {
+        files.push(render(template, &values(first_country)));
+    }
+    if let Some(template) = config.per_group.as_deref() {
+        files.push(render(template, &values(first_country)));
+    }
+    if let Some(template) = config.per_country.as_deref() {
+        if countries.is_empty() {
+            files.push(render(template, &values(NO_COUNTRY)));
+        }
+        for country in &countries {
+            files.push(render(template, &values(country)));
+        }
+    }
+
+    let mut seen = HashSet::new();
+    files.retain(|file| seen.insert(file.clone()));
+    files
+}
+
+/// `template` with each `{name}` replaced by its value, made safe to use in
+/// a file name
+fn render(template: &str, values: &[(&str, String)]) -> String {
+    values.iter().fold(template.to_string(), |file, (name, value)| {
+        file.replace(&format!("{{{}}}", name), &file_name_part(value))
+    })
+}
+
+/// `value` with anything but letters, digits, `-`, `_` and inner dots
+/// replaced, so it cannot leave the output directory
+fn file_name_part(value: &str) -> String {
+    let part: String = value.trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect();
+    let part = part.trim_matches('.');
+    if part.is_empty() { "_".to_string() } else { part.to_string() }
+}
+
+/// Replace a file through a temporary file next to it, so readers see the
+/// old or the new content and never part of it
+fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+
+    fs::write(&temp, content).with_context(|| format!("Failed to write {:?}", temp))?;
+    fs::rename(&temp, path).with_context(|| format!("Failed to replace {:?}", path))
+}
+
+/// Append entries to a playlist, starting it with a header if it is new
+fn append_entries(path: &Path, header: &Header, channels: &[&Channel]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {:?}", path))?;
+
+    // Judged on the opened file, which exists by now even if it is new
+    let len = file.metadata()?.len();
+    let ends_with_newline = len == 0 || {
+        let mut last = [0u8; 1];
+        file.seek(SeekFrom::End(-1))?;
+        file.read_exact(&mut last)?;
+        last[0] == b'\n'
+    };
+
+    let mut writer = BufWriter::new(file);
+    if len == 0 {
+        writeln!(writer, "{}", header)?;
+    } else if !ends_with_newline {
+        // An interrupted write left a partial line; end it before the next entry
+        writeln!(writer)?;
+    }
+    for channel in channels {
+        writeln!(writer, "{}", channel.to_entry())?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::task::JoinSet;
+
+    const SERVERS: usize = 2;
+    const CHANNELS: usize = 50;
+
+    /// Fresh output directory with per-server and per-group playlists
+    fn output(name: &str) -> OutputConfig {
+        let dir = std::env::temp_dir().join(format!("astra-scanner-catalog-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        OutputConfig {
+            dir: dir.to_string_lossy().into_owned(),
+            per_server: Some("servers/{server}.m3u8".to_string()),
+            per_group: Some("groups/{group}.m3u8".to_string()),
+            ..OutputConfig::default()
+        }
+    }
+
+    /// Every channel of a server's playlist; even ones are in the `News` group
+    fn server_channels(server: usize) -> Vec<Channel> {
+        let base_url = format!("http://10.0.0.{}:8000", server);
+        let mut text = String::from("#EXTM3U\n");
+        for index in 0..CHANNELS {
+            let group = if index % 2 == 0 { " group-title=\"News\"" } else { "" };
+            text.push_str(&format!("#EXTINF:-1{},Channel {}\n{}/ch{}\n", group, index, base_url, index));
+        }
+        m3u::parse(&text).entries.iter()
+            .map(|entry| Channel::from_entry(entry, &entry.uri, Some(&base_url)))
+            .collect()
+    }
+
+    /// URLs of a playlist under the output directory, in file order
+    fn urls(config: &OutputConfig, file: &str) -> Vec<String> {
+        m3u::parse_file(&Path::new(&config.dir).join(file)).unwrap().uris().map(str::to_string).collect()
+    }
+
+    fn assert_unique(urls: &[String], expected: usize) {
+        assert_eq!(urls.len(), expected);
+        assert_eq!(urls.iter().collect::<HashSet<_>>().len(), expected);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_appends_are_written_once_per_playlist() {
+        let config = output("concurrent");
+        let writer = CatalogWriter::spawn(config.clone()).unwrap();
+
+        // Every server's playlist is sent by several tasks at once, in pieces
+        let mut tasks = JoinSet::new();
+        for task in 0..8 {
+            let writer = writer.clone();
+            tasks.spawn(async move {
+                let mut new_channels = 0;
+                for chunk in server_channels(task % SERVERS).chunks(7) {
+                    new_channels += writer.append(chunk.to_vec()).await.unwrap();
+                }
+                new_channels
+            });
+        }
+        let mut new_channels = 0;
+        while let Some(result) = tasks.join_next().await {
+            new_channels += result.unwrap();
+        }
+        writer.flush().await.unwrap();
+
+        assert_eq!(new_channels, SERVERS * CHANNELS);
+        assert_unique(&urls(&config, &config.combined), SERVERS * CHANNELS);
+        for server in 0..SERVERS {
+            assert_unique(&urls(&config, &format!("servers/10.0.0.{}_8000.m3u8", server)), CHANNELS);
+        }
+        assert_unique(&urls(&config, "groups/News.m3u8"), SERVERS * CHANNELS / 2);
+        assert_unique(&urls(&config, &format!("groups/{}.m3u8", NO_GROUP)), SERVERS * CHANNELS / 2);
+
+        let combined = fs::read_to_string(combined_path(&config)).unwrap();
+        assert_eq!(combined.matches("#EXTM3U").count(), 1);
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_new_writer_indexes_the_playlists_on_disk() {
+        let config = output("reopen");
+        let writer = CatalogWriter::spawn(config.clone()).unwrap();
+        assert_eq!(writer.append(server_channels(0)).await.unwrap(), CHANNELS);
+        writer.flush().await.unwrap();
+        drop(writer);
+
+        // A playlist removed since is written again, and only that one
+        fs::remove_file(Path::new(&config.dir).join("groups/News.m3u8")).unwrap();
+
+        let writer = CatalogWriter::spawn(config.clone()).unwrap();
+        assert_eq!(writer.append(server_channels(0)).await.unwrap(), 0);
+        writer.flush().await.unwrap();
+
+        assert_unique(&urls(&config, &config.combined), CHANNELS);
+        assert_unique(&urls(&config, "servers/10.0.0.0_8000.m3u8"), CHANNELS);
+        assert_unique(&urls(&config, "groups/News.m3u8"), CHANNELS / 2);
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+}
@@ -1,47 +1,243 @@
 use std::time::Duration;
+use chrono::{DateTime, Local};
+use futures::StreamExt;
 use reqwest::Client;
-use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
-/// Verifies if a single channel URL is working
-pub async fn check_channel(url: &str) -> bool {
-    // This is a stub implementation
-    println!("Checking channel {}", url);
-    true
+use crate::config::ScannerConfig;
+use crate::http;
+use crate::m3u::{self, Attributes, Entry, Extinf};
+
+/// URL schemes the stream check can open
+pub const VERIFIABLE_SCHEMES: [&str; 2] = ["http", "https"];
+
+/// Quality tags left out of a name's canonical form when they end it
+const QUALITY_SUFFIXES: [&str; 14] = [
+    "hd", "fhd", "uhd", "sd", "hq", "4k", "8k", "hevc", "h264", "h265", "1080p", "1080i", "720p", "576i",
+];
+
+/// A channel listed in a server's playlist
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Channel {
+    /// Display name
+    pub name: String,
+    pub url: String,
+    /// `group-title` or `#EXTGRP` group
+    pub group: Option<String>,
+    /// `tvg-logo` URL
+    pub logo: Option<String>,
+    /// `tvg-id`, the channel's EPG identifier
+    pub tvg_id: Option<String>,
+    /// Base URL of the server whose playlist listed the channel
+    pub server: Option<String>,
+    pub first_seen: DateTime<Local>,
+    pub last_seen: DateTime<Local>,
+    /// Outcome of the last check of the stream, if any
+    pub verification: Option<Verification>,
+    /// All `#EXTINF` attributes as the playlist had them, e.g. `tvg-name`,
+    /// `tvg-country` or `catchup`
+    #[serde(default)]
+    pub attributes: Attributes,
+    /// `#EXTVLCOPT` options the stream needs, e.g. `http-user-agent`
+    #[serde(default)]
+    pub vlc_options: Attributes,
+    /// `#KODIPROP` properties
+    #[serde(default)]
+    pub kodi_props: Attributes,
+    /// Other directives of the entry, verbatim
+    #[serde(default)]
+    pub other_tags: Vec<String>,
 }
 
-/// Verifies multiple channels concurrently
-#[allow(dead_code)]
-pub async fn verify_channels(channels: &[String], server: &str) -> Vec<String> {
-    // This is a stub implementation
-    println!("Verifying {} channels for server {}", channels.len(), server);
-    channels.to_vec()
+/// Result of checking that a channel's stream plays
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Verification {
+    pub checked_at: DateTime<Local>,
+    pub working: bool,
 }
 
-/// Parse M3U playlist content into channels
-#[allow(dead_code)]
-pub fn parse_playlist(content: &str) -> Vec<(String, String)> {
-    let mut channels = Vec::new();
-    let mut current_title = String::new();
-    
-    for line in content.lines() {
-        if line.starts_with("#EXTINF:") {
-            current_title = line.to_string();
-        } else if line.starts_with("http://") || line.starts_with("https://") {
-            if !current_title.is_empty() {
-                channels.push((current_title.clone(), line.to_string()));
-                current_title = String::new();
-            }
+impl Channel {
+    /// Channel for a playlist entry, seen now. `url` is the entry's URI
+    /// resolved against the playlist URL.
+    pub fn from_entry(entry: &Entry, url: &str, server: Option<&str>) -> Self {
+        let now = Local::now();
+
+        Self {
+            name: m3u::sanitize_title(entry.title()),
+            url: url.to_string(),
+            group: entry.group().map(str::to_string),
+            logo: entry.tvg_logo().map(str::to_string),
+            tvg_id: entry.tvg_id().map(str::to_string),
+            server: server.map(str::to_string),
+            first_seen: now,
+            last_seen: now,
+            verification: None,
+            attributes: entry.extinf.as_ref().map(|extinf| extinf.attributes.clone()).unwrap_or_default(),
+            vlc_options: entry.vlc_options.clone(),
+            kodi_props: entry.kodi_props.clone(),
+            other_tags: entry.other_tags.clone(),
+        }
+    }
+
+    /// Record the outcome of a stream check
+    pub fn verified(mut self, working: bool) -> Self {
+        self.verification = Some(Verification {
+            checked_at: Local::now(),
+            working,
+        });
+        self
+    }
+
+    /// Whether the last check found the stream playing; `None` if unchecked
+    pub fn is_working(&self) -> Option<bool> {
+        self.verification.as_ref().map(|verification| verification.working)
+    }
+
+    /// Lowercased scheme of the stream URL, e.g. `udp` or `rtmp`
+    pub fn scheme(&self) -> Option<String> {
+        url_scheme(&self.url).map(str::to_ascii_lowercase)
+    }
+
+    /// Whether the stream check can open the channel's URL. Channels with
+    /// other schemes, such as multicast `udp://@` streams, are kept but never
+    /// verified.
+    pub fn is_verifiable(&self) -> bool {
+        self.scheme().is_some_and(|scheme| VERIFIABLE_SCHEMES.contains(&scheme.as_str()))
+    }
+
+    /// Key shared by every source of the same logical channel: the
+    /// `tvg-id` if it has one, else the name as `normalize_name` reduces it
+    pub fn canonical_key(&self) -> String {
+        match self.tvg_id.as_deref().map(str::trim).filter(|tvg_id| !tvg_id.is_empty()) {
+            Some(tvg_id) => format!("id:{}", tvg_id.to_lowercase()),
+            None => format!("name:{}", normalize_name(&self.name)),
         }
     }
-    
-    channels
+
+    /// `tvg-country` codes, which may list several countries
+    pub fn countries(&self) -> Vec<&str> {
+        self.attributes.get("tvg-country")
+            .map(|countries| countries.split([';', ',']).map(str::trim).filter(|country| !country.is_empty()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Playlist entry for the channel, with its original attributes and
+    /// options. The name, `tvg-id`, `tvg-logo` and group come from the
+    /// channel's fields, the group as `group-title`.
+    pub fn to_entry(&self) -> Entry {
+        let mut extinf = Extinf::untitled(&self.name);
+        extinf.attributes = self.attributes.clone();
+        if let Some(tvg_id) = &self.tvg_id {
+            extinf.attributes.set("tvg-id", tvg_id.as_str());
+        }
+        if let Some(logo) = &self.logo {
+            extinf.attributes.set("tvg-logo", logo.as_str());
+        }
+        if let Some(group) = &self.group {
+            extinf.attributes.set("group-title", group.as_str());
+        }
+
+        Entry {
+            line: 0,
+            extinf: Some(extinf),
+            group: None,
+            vlc_options: self.vlc_options.clone(),
+            kodi_props: self.kodi_props.clone(),
+            other_tags: self.other_tags.clone(),
+            uri: self.url.clone(),
+        }
+    }
+
+    /// Whether the name or group contains `query`, ignoring case
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        query.is_empty()
+            || self.name.to_lowercase().contains(&query)
+            || self.group.as_ref().is_some_and(|group| group.to_lowercase().contains(&query))
+    }
 }
 
-/// Extract channel name from EXTINF line
+/// Whether a channel's stream plays: a successful `HEAD`, or else a `GET`
+/// that sends stream data within `channel_sample_ms`. HLS playlists only get
+/// the `HEAD`.
+pub async fn check_channel(client: &Client, url: &str, config: &ScannerConfig) -> bool {
+    let timeout = Duration::from_secs(config.channel_timeout as u64);
+
+    match client.head(url).timeout(timeout).send().await {
+        Ok(response) if response.status().is_success() => return true,
+        _ => {}
+    }
+    if url.to_lowercase().contains(".m3u8") {
+        return false;
+    }
+
+    let response = match client.get(url).timeout(timeout).send().await {
+        Ok(response) if response.status().is_success() => response,
+        _ => return false,
+    };
+
+    // Only a sample: a live stream never ends
+    let sample_time = Duration::from_millis(config.channel_sample_ms);
+    match http::read_bounded(response, config.channel_sample_bytes, sample_time).await {
+        Ok(sample) => !sample.bytes.is_empty(),
+        Err(_) => false,
+    }
+}
+
+/// Check channels, `channel_concurrency` at a time, and return them in the
+/// same order with their verification. Channels whose scheme cannot be
+/// checked come back unverified.
+pub async fn verify_channels(client: &Client, channels: Vec<Channel>, config: &ScannerConfig) -> Vec<Channel> {
+    futures::stream::iter(channels)
+        .map(|channel| async move {
+            if !channel.is_verifiable() {
+                return channel;
+            }
+            let working = check_channel(client, &channel.url, config).await;
+            channel.verified(working)
+        })
+        .buffered(config.channel_concurrency.max(1))
+        .collect()
+        .await
+}
+
+/// Parse M3U playlist content into channels, whatever their URL scheme
 #[allow(dead_code)]
-pub fn extract_channel_name(extinf: &str) -> String {
-    if let Some(pos) = extinf.rfind(',') {
-        return extinf[pos + 1..].trim().to_string();
+pub fn parse_playlist(content: &str) -> Vec<Channel> {
+    m3u::parse(content).entries.iter()
+        .map(|entry| Channel::from_entry(entry, entry.uri.trim(), None))
+        .collect()
+}
+
+/// A channel name reduced to what identifies the channel: without accents
+/// or case, with punctuation and runs of whitespace turned into single
+/// spaces, and without quality tags such as `HD` or `FHD` at the end.
+///
+/// `Canal+ 1 (FHD)` and `canal+  1 hd` both become `canal+ 1`.
+pub fn normalize_name(name: &str) -> String {
+    let folded = name.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect::<String>()
+        .to_lowercase();
+
+    let mut words: Vec<&str> = folded
+        .split(|c: char| !(c.is_alphanumeric() || c == '+'))
+        .filter(|word| !word.is_empty())
+        .collect();
+    while words.len() > 1 && words.last().is_some_and(|word| QUALITY_SUFFIXES.contains(word)) {
+        words.pop();
     }
-    extinf.to_string()
-} 
\ No newline at end of file
+
+    words.join(" ")
+}
+
+/// Scheme of an absolute URL such as `rtp://...` or `udp://@...`, as
+/// written; `None` for relative URLs
+pub fn url_scheme(url: &str) -> Option<&str> {
+    let (scheme, _) = url.trim().split_once(':')?;
+    let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    valid.then_some(scheme)
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::path::Path;
@@ -11,6 +12,12 @@ pub struct Config {
     pub asn: ASNConfig,
     pub language: LanguageConfig,
     pub app: AppConfig,
+    #[serde(default)]
+    pub probe: ProbeConfig,
+    #[serde(default)]
+    pub output: OutputConfig,
+    #[serde(default)]
+    pub epg: EpgConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -26,6 +33,158 @@ pub struct ScannerConfig {
     pub channel_timeout: usize,
     pub pool_connections: usize,
     pub pool_maxsize: usize,
+    /// Ports probed over TLS before falling back to plain HTTP
+    #[serde(default = "default_tls_ports")]
+    pub tls_ports: Vec<u16>,
+    /// Local IP address or interface name scan traffic is sent from
+    #[serde(default)]
+    pub source_address: Option<String>,
+    /// Bytes read from a live stream before a channel counts as working
+    #[serde(default = "default_channel_sample_bytes")]
+    pub channel_sample_bytes: usize,
+    /// Milliseconds a channel check waits for stream data
+    #[serde(default = "default_channel_sample_ms")]
+    pub channel_sample_ms: u64,
+    /// Channels checked at the same time
+    #[serde(default = "default_channel_concurrency")]
+    pub channel_concurrency: usize,
+    /// Largest playlist body accepted, in bytes
+    #[serde(default = "default_playlist_max_bytes")]
+    pub playlist_max_bytes: usize,
+    /// Hits scoring below this (0-100) are kept as unconfirmed and their
+    /// playlists are not fetched
+    #[serde(default = "default_confidence_threshold")]
+    pub confidence_threshold: u8,
+    /// Playlist paths to try, in order, by detector name; replaces the
+    /// detector's own paths
+    #[serde(default)]
+    pub playlist_paths: BTreeMap<String, Vec<String>>,
+}
+
+fn default_tls_ports() -> Vec<u16> {
+    vec![443, 8443]
+}
+
+fn default_channel_sample_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_channel_sample_ms() -> u64 {
+    1500
+}
+
+fn default_channel_concurrency() -> usize {
+    20
+}
+
+fn default_confidence_threshold() -> u8 {
+    70
+}
+
+fn default_playlist_max_bytes() -> usize {
+    8 * 1024 * 1024
+}
+
+/// How the scanner identifies itself and what it requests when probing.
+///
+/// Applied to every request the scanner sends.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ProbeConfig {
+    /// Left out of saved configs while it is the default, so it follows the
+    /// version
+    #[serde(skip_serializing_if = "is_default_user_agent")]
+    pub user_agent: String,
+    /// Contact address sent in the `From` header
+    pub from: Option<String>,
+    pub extra_headers: BTreeMap<String, String>,
+    pub method: ProbeMethod,
+    /// Path requested by the fingerprint probe
+    pub path: String,
+}
+
+/// Request method for fingerprint probes.
+///
+/// `Head` falls back to `GET` when the server answers 405.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ProbeMethod {
+    Head,
+    Get,
+}
+
+/// Page explaining the scan and how to opt out, linked from the User-Agent.
+/// It is the only contact a probe carries when `from` is not set.
+pub const OPT_OUT_URL: &str = "https://github.com/andremxmx/test#opt-out";
+
+impl ProbeConfig {
+    /// Contact address for the `From` header, if one is set
+    pub fn contact(&self) -> Option<&str> {
+        self.from.as_deref().map(str::trim).filter(|from| !from.is_empty())
+    }
+}
+
+fn default_user_agent() -> String {
+    format!("astra-scanner/{} (+{})", env!("CARGO_PKG_VERSION"), OPT_OUT_URL)
+}
+
+fn is_default_user_agent(user_agent: &String) -> bool {
+    *user_agent == default_user_agent()
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: default_user_agent(),
+            from: None,
+            extra_headers: BTreeMap::new(),
+            method: ProbeMethod::Head,
+            path: "/".to_string(),
+        }
+    }
+}
+
+/// Playlists working channels are saved to.
+///
+/// File names are templates relative to `dir`. They may use `{server}`
+/// (host and port), `{host}`, `{port}`, `{group}` and `{country}` (from
+/// `tvg-country`), and may contain subdirectories. A split playlist is only
+/// written when its template is set.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct OutputConfig {
+    pub dir: String,
+    /// Playlist with every working channel
+    pub combined: String,
+    /// One playlist per server, e.g. `servers/{host}_{port}.m3u8`
+    pub per_server: Option<String>,
+    /// One playlist per channel group, e.g. `groups/{group}.m3u8`
+    pub per_group: Option<String>,
+    /// One playlist per country, e.g. `countries/{country}.m3u8`
+    pub per_country: Option<String>,
+    /// Guide players are pointed to in the playlists' `x-tvg-url` header
+    pub tvg_url: Option<String>,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            dir: "channels".to_string(),
+            combined: "all_channels.m3u8".to_string(),
+            per_server: None,
+            per_group: None,
+            per_country: None,
+            tvg_url: None,
+        }
+    }
+}
+
+/// Electronic programme guide channels are matched against
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct EpgConfig {
+    /// Local XMLTV file
+    pub file: Option<String>,
 }
 
 /// Simple config structure for GUI mode
@@ -66,6 +225,14 @@ impl Default for Config {
                 channel_timeout: 2,
                 pool_connections: 50,
                 pool_maxsize: 50,
+                tls_ports: default_tls_ports(),
+                source_address: None,
+                channel_sample_bytes: default_channel_sample_bytes(),
+                channel_sample_ms: default_channel_sample_ms(),
+                channel_concurrency: default_channel_concurrency(),
+                playlist_max_bytes: default_playlist_max_bytes(),
+                confidence_threshold: default_confidence_threshold(),
+                playlist_paths: BTreeMap::new(),
             },
             asn: ASNConfig {
                 max_workers: 20,
@@ -77,6 +244,9 @@ impl Default for Config {
             app: AppConfig {
                 lang: "en".to_string(),
             },
+            probe: ProbeConfig::default(),
+            output: OutputConfig::default(),
+            epg: EpgConfig::default(),
         }
     }
 }
@@ -131,4 +301,37 @@ impl Config {
             
         Ok(())
     }
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_config_uses_the_versioned_user_agent() {
+        let shipped: serde_json::Value = serde_json::from_str(include_str!("../pool/config.json")).unwrap();
+        let probe: ProbeConfig = serde_json::from_value(shipped["probe"].clone()).unwrap();
+        assert_eq!(probe.user_agent, default_user_agent());
+        assert!(probe.user_agent.contains(env!("CARGO_PKG_VERSION")));
+    }
+
+    #[test]
+    fn default_user_agent_is_not_saved() {
+        let saved = serde_json::to_value(Config::default()).unwrap();
+        assert!(saved["probe"].get("user_agent").is_none());
+
+        let mut config = Config::default();
+        config.probe.user_agent = "research-scanner/2.0".to_string();
+        let saved = serde_json::to_value(&config).unwrap();
+        assert_eq!(saved["probe"]["user_agent"], "research-scanner/2.0");
+    }
+
+    #[test]
+    fn blank_contact_is_no_contact() {
+        let mut probe = ProbeConfig::default();
+        assert_eq!(probe.contact(), None);
+        probe.from = Some("  ".to_string());
+        assert_eq!(probe.contact(), None);
+        probe.from = Some(" abuse@example.net ".to_string());
+        assert_eq!(probe.contact(), Some("abuse@example.net"));
+    }
+}
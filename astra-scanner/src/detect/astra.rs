@@ -0,0 +1,169 @@
+use std::time::Duration;
+use async_trait::async_trait;
+use reqwest::Client;
+
+use crate::playlist::Protection;
+
+use super::{confirm_missing_path, fetch_sample, Confirmation, Detection, Detector, Observation, ATTR_BUILD, ATTR_EDITION, ATTR_PRODUCT, ATTR_VERSION};
+
+/// Editions that show up as words in Astra `Server` headers
+const EDITIONS: &[&str] = &["pro", "lite", "free", "trial", "enterprise", "dev"];
+
+/// Confidence from the `Server` header alone, which anything can echo
+const HEADER_CONFIDENCE: u8 = 60;
+
+/// Bytes of the playlist read to check that it is one
+const PLAYLIST_SAMPLE_BYTES: usize = 4096;
+
+const PLAYLIST_PATH: &str = "/playlist.m3u";
+
+/// Playlist paths in order of preference; some builds only serve the `.m3u8` name
+const PLAYLIST_PATHS: [&str; 2] = [PLAYLIST_PATH, "/playlist.m3u8"];
+
+/// Cesbo Astra, identified by its `Server` header.
+///
+/// The match is confirmed by fetching `/playlist.m3u` and, failing that, by
+/// requesting a path that cannot exist: Astra answers it with a 404 carrying
+/// its own `Server` header, while catch-all honeypots answer 200.
+pub struct AstraDetector;
+
+#[async_trait]
+impl Detector for AstraDetector {
+    fn name(&self) -> &str {
+        "astra"
+    }
+
+    fn detect(&self, observation: &Observation) -> Option<Detection> {
+        let server = observation.header("server")?;
+        let version = parse_server_header(server)?;
+
+        let mut detection = Detection::new(self.name(), HEADER_CONFIDENCE)
+            .with_attribute("server", server)
+            .with_attribute(ATTR_PRODUCT, "Astra");
+        for (key, value) in [(ATTR_VERSION, version.version), (ATTR_BUILD, version.build), (ATTR_EDITION, version.edition)] {
+            if let Some(value) = value {
+                detection = detection.with_attribute(key, value);
+            }
+        }
+
+        Some(detection)
+    }
+
+    fn playlist_paths(&self) -> Vec<String> {
+        PLAYLIST_PATHS.iter().map(|path| path.to_string()).collect()
+    }
+
+    async fn confirm(&self, client: &Client, base_url: &str, _detection: &Detection, timeout: Duration) -> Option<Confirmation> {
+        let playlist_url = format!("{}{}", base_url, PLAYLIST_PATH);
+        let mut protection = None;
+        if let Some((status, headers, body)) = fetch_sample(client, &playlist_url, PLAYLIST_SAMPLE_BYTES, timeout).await {
+            if status.is_success() && body.contains("#EXTM3U") {
+                return Some(Confirmation::new(95, "/playlist.m3u is an M3U playlist"));
+            }
+            protection = Protection::of_response(PLAYLIST_PATH, status, &headers);
+        }
+
+        let confirmation = confirm_missing_path(client, base_url, "Astra", HEADER_CONFIDENCE, timeout).await;
+        Some(confirmation.with_protection(protection))
+    }
+}
+
+/// Version details from an Astra `Server` header
+#[derive(Debug, Default, PartialEq, Eq)]
+struct AstraVersion {
+    version: Option<String>,
+    build: Option<String>,
+    edition: Option<String>,
+}
+
+/// Parse headers like `Astra/5.64-rc1`, `Cesbo Astra 2022.12.21 (Pro)` or
+/// `Astra/5.62 build 1045`. Returns `None` if the header is not Astra's.
+fn parse_server_header(server: &str) -> Option<AstraVersion> {
+    let rest = &server[server.find("Astra")? + "Astra".len()..];
+    let mut parsed = AstraVersion::default();
+
+    let mut tokens = rest
+        .split(|c: char| c == '/' || c == '(' || c == ')' || c == ';' || c == ',' || c.is_whitespace())
+        .filter(|token| !token.is_empty());
+
+    while let Some(token) = tokens.next() {
+        let lower = token.to_lowercase();
+
+        if lower == "build" {
+            parsed.build = tokens.next().map(str::to_string);
+        } else if let Some(build) = lower.strip_prefix("build").map(|b| b.trim_start_matches(['-', '.', ':'])) {
+            if !build.is_empty() {
+                parsed.build = Some(build.to_string());
+            }
+        } else if EDITIONS.contains(&lower.as_str()) {
+            parsed.edition = Some(token.to_string());
+        } else if parsed.version.is_none() && token.starts_with(|c: char| c.is_ascii_digit()) {
+            // A suffix after `-` or `+` is the build, e.g. `5.64-rc1`
+            match token.split_once(['-', '+']) {
+                Some((version, build)) => {
+                    parsed.version = Some(version.to_string());
+                    if !build.is_empty() {
+                        parsed.build.get_or_insert_with(|| build.to_string());
+                    }
+                }
+                None => parsed.version = Some(token.to_string()),
+            }
+        } else if parsed.build.is_none() && is_revision(&lower) {
+            parsed.build = Some(token.to_string());
+        }
+    }
+
+    Some(parsed)
+}
+
+/// Revision tokens such as `r1045`
+fn is_revision(token: &str) -> bool {
+    token.strip_prefix('r')
+        .is_some_and(|digits| !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: Option<&str>, build: Option<&str>, edition: Option<&str>) -> Option<AstraVersion> {
+        Some(AstraVersion {
+            version: version.map(str::to_string),
+            build: build.map(str::to_string),
+            edition: edition.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn real_server_headers() {
+        assert_eq!(parse_server_header("Astra/5.64-rc1"), version(Some("5.64"), Some("rc1"), None));
+        assert_eq!(parse_server_header("Astra/5.62 build 1045"), version(Some("5.62"), Some("1045"), None));
+        assert_eq!(parse_server_header("Cesbo Astra 2022.12.21 (Pro)"), version(Some("2022.12.21"), None, Some("Pro")));
+        assert_eq!(parse_server_header("Astra/4.4.182"), version(Some("4.4.182"), None, None));
+        assert_eq!(parse_server_header("Astra/5.62 r1045 lite"), version(Some("5.62"), Some("r1045"), Some("lite")));
+        assert_eq!(parse_server_header("Astra/5.63+2891; trial"), version(Some("5.63"), Some("2891"), Some("trial")));
+        assert_eq!(parse_server_header("Astra/5.60 build-1020"), version(Some("5.60"), Some("1020"), None));
+    }
+
+    #[test]
+    fn explicit_build_wins_over_version_suffix() {
+        assert_eq!(parse_server_header("Astra/5.64-rc1 build 2001"), version(Some("5.64"), Some("2001"), None));
+    }
+
+    #[test]
+    fn malformed_headers() {
+        assert_eq!(parse_server_header("Astra"), version(None, None, None));
+        assert_eq!(parse_server_header("Astra/"), version(None, None, None));
+        assert_eq!(parse_server_header("Astra/beta"), version(None, None, None));
+        assert_eq!(parse_server_header("Astra/5.62-"), version(Some("5.62"), None, None));
+        assert_eq!(parse_server_header("Astra/5.62 build"), version(Some("5.62"), None, None));
+        assert_eq!(parse_server_header("Astra/ (((Pro"), version(None, None, Some("Pro")));
+    }
+
+    #[test]
+    fn other_servers_are_not_astra() {
+        assert_eq!(parse_server_header("nginx/1.18.0"), None);
+        assert_eq!(parse_server_header("Flussonic/21.02"), None);
+        assert_eq!(parse_server_header(""), None);
+    }
+}
@@ -0,0 +1,35 @@
+use std::time::Duration;
+use async_trait::async_trait;
+use reqwest::Client;
+
+use super::{confirm_missing_path, product_detection, product_version, Confirmation, Detection, Detector, Observation};
+
+/// Confidence from the `Server` header alone
+const HEADER_CONFIDENCE: u8 = 60;
+
+/// Flussonic Media Server, identified by its `Server` header
+/// (`Flussonic/23.03.1`).
+///
+/// Its playlists are per stream and its API needs credentials, so the match
+/// is confirmed with the missing-path check only.
+pub struct FlussonicDetector;
+
+#[async_trait]
+impl Detector for FlussonicDetector {
+    fn name(&self) -> &str {
+        "flussonic"
+    }
+
+    fn detect(&self, observation: &Observation) -> Option<Detection> {
+        let server = observation.header("server")?;
+        if !server.contains("Flussonic") {
+            return None;
+        }
+
+        Some(product_detection(self.name(), HEADER_CONFIDENCE, "Flussonic", server, product_version(server, "Flussonic")))
+    }
+
+    async fn confirm(&self, client: &Client, base_url: &str, _detection: &Detection, timeout: Duration) -> Option<Confirmation> {
+        Some(confirm_missing_path(client, base_url, "Flussonic", HEADER_CONFIDENCE, timeout).await)
+    }
+}
@@ -0,0 +1,546 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, SERVER};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{ProbeConfig, ProbeMethod};
+use crate::http::{self, ResponseMeta};
+use crate::playlist::Protection;
+use crate::store::{RawResponse, RawResponseLog};
+use crate::tls::CertificateInfo;
+
+pub mod astra;
+pub mod flussonic;
+pub mod nginx_rtmp;
+pub mod rules;
+pub mod tvheadend;
+pub mod udpxy;
+pub mod wowza;
+
+/// Most body bytes kept from a probe response for matching
+const BODY_SAMPLE_BYTES: usize = 16 * 1024;
+
+/// Attribute keys copied into the typed fields of `scanner::Server`
+pub const ATTR_PRODUCT: &str = "product";
+pub const ATTR_VERSION: &str = "version";
+pub const ATTR_BUILD: &str = "build";
+pub const ATTR_EDITION: &str = "edition";
+
+/// Request a detector wants sent to a target
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Probe {
+    pub method: ProbeMethod,
+    pub path: String,
+}
+
+impl Probe {
+    pub fn new(method: ProbeMethod, path: &str) -> Self {
+        Self {
+            method,
+            path: path.to_string(),
+        }
+    }
+
+    /// The request configured in the probe profile
+    pub fn from_profile(profile: &ProbeConfig) -> Self {
+        Self::new(profile.method, &profile.path)
+    }
+}
+
+/// What a target answered to a probe
+#[derive(Debug, Clone)]
+pub struct Observation {
+    pub base_url: String,
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    /// Start of the body, at most `BODY_SAMPLE_BYTES`; empty for `HEAD`
+    pub body: Vec<u8>,
+    pub certificate: Option<CertificateInfo>,
+    pub meta: ResponseMeta,
+}
+
+impl Observation {
+    /// Value of a header, if present and valid text
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    /// Rebuild the observation stored in a raw response.
+    ///
+    /// Headers that are no longer valid are dropped; the body is the capped
+    /// sample that was stored.
+    pub fn from_raw(raw: &RawResponse) -> Self {
+        let headers = raw.meta.headers.iter()
+            .filter_map(|(name, value)| Some((
+                HeaderName::from_bytes(name.as_bytes()).ok()?,
+                HeaderValue::from_str(value).ok()?,
+            )))
+            .collect();
+
+        Self {
+            base_url: raw.base_url.clone(),
+            status: StatusCode::from_u16(raw.status).unwrap_or(StatusCode::OK),
+            headers,
+            body: raw.body_bytes(),
+            certificate: raw.certificate.clone(),
+            meta: raw.meta.clone(),
+        }
+    }
+}
+
+/// A service identified on a target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Detection {
+    /// Name of the detector that matched
+    pub detector: String,
+    /// Confidence in the match, 0-100
+    pub confidence: u8,
+    /// Values extracted from the response, e.g. the `Server` header
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+    /// Outcome of the confirmation probe, if the detector has one
+    #[serde(default)]
+    pub confirmation: Option<Confirmation>,
+}
+
+impl Detection {
+    pub fn new(detector: &str, confidence: u8) -> Self {
+        Self {
+            detector: detector.to_string(),
+            confidence: confidence.min(100),
+            attributes: BTreeMap::new(),
+            confirmation: None,
+        }
+    }
+
+    pub fn with_attribute(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.attributes.insert(key.to_string(), value.into());
+        self
+    }
+}
+
+/// Result of a second, independent probe sent after a match
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Confirmation {
+    /// Confidence in the service given everything seen, 0-100. Replaces the
+    /// confidence of the initial match; 0 rejects the match altogether.
+    pub confidence: u8,
+    /// What the probe found, for reports
+    pub evidence: String,
+    /// Values the probe extracted, merged into the detection's attributes
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+    /// Playlist path the probe found protected, which the playlist fetch
+    /// then does not request again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protection: Option<Protection>,
+}
+
+impl Confirmation {
+    pub fn new(confidence: u8, evidence: impl Into<String>) -> Self {
+        Self {
+            confidence: confidence.min(100),
+            evidence: evidence.into(),
+            attributes: BTreeMap::new(),
+            protection: None,
+        }
+    }
+
+    pub fn with_attribute(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.attributes.insert(key.to_string(), value.into());
+        self
+    }
+
+    pub fn with_protection(mut self, protection: Option<Protection>) -> Self {
+        self.protection = protection;
+        self
+    }
+}
+
+/// Fingerprint for one kind of service.
+///
+/// A detector names the probes it needs and matches the responses to them.
+/// Detectors sharing a probe see the same response, so each distinct probe is
+/// sent once per target. A detector may also confirm its best match with a
+/// probe that a header-echoing proxy or honeypot would not pass.
+#[async_trait]
+pub trait Detector: Send + Sync {
+    /// Short, stable name, used in results and exports
+    fn name(&self) -> &str;
+
+    /// Probes to send; the probe profile's request by default
+    fn probes(&self, profile: &ProbeConfig) -> Vec<Probe> {
+        vec![Probe::from_profile(profile)]
+    }
+
+    /// Match a response to one of this detector's probes
+    fn detect(&self, observation: &Observation) -> Option<Detection>;
+
+    /// Paths where the product publicly serves M3U playlists, in order of
+    /// preference; empty if it has none
+    fn playlist_paths(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Probe `base_url` again to confirm `detection`; `None` if the detector
+    /// has no confirmation probe, which keeps the initial confidence
+    async fn confirm(&self, _client: &Client, _base_url: &str, _detection: &Detection, _timeout: Duration) -> Option<Confirmation> {
+        None
+    }
+}
+
+/// Positive result of running the registry against a target
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub base_url: String,
+    pub detection: Detection,
+    pub certificate: Option<CertificateInfo>,
+    /// Playlist endpoints of the detected product
+    pub playlist_paths: Vec<String>,
+    /// Response to the probe that matched
+    pub response: ResponseMeta,
+}
+
+/// Detectors run against every target
+pub struct DetectorRegistry {
+    detectors: Vec<Arc<dyn Detector>>,
+    rules: Option<rules::RuleSet>,
+    raw_log: Option<Arc<RawResponseLog>>,
+}
+
+impl DetectorRegistry {
+    pub fn new() -> Self {
+        Self {
+            detectors: Vec::new(),
+            rules: None,
+            raw_log: None,
+        }
+    }
+
+    /// Registry with the built-in detectors
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(astra::AstraDetector);
+        registry.register(flussonic::FlussonicDetector);
+        registry.register(udpxy::UdpxyDetector);
+        registry.register(tvheadend::TvheadendDetector);
+        registry.register(wowza::WowzaDetector);
+        registry.register(nginx_rtmp::NginxRtmpDetector);
+        registry
+    }
+
+    /// Built-in detectors plus the rules in `pool/fingerprints.json`.
+    ///
+    /// Fails if the rules file is invalid. Later edits to the file are picked
+    /// up while scanning.
+    pub fn load() -> Result<Self> {
+        let mut registry = Self::builtin();
+        registry.rules = Some(rules::RuleSet::load(rules::FINGERPRINTS_PATH)?);
+        Ok(registry)
+    }
+
+    /// Save every probe response to `log`, matched or not, so targets can be
+    /// re-fingerprinted later without scanning them again
+    pub fn with_raw_log(mut self, log: Arc<RawResponseLog>) -> Self {
+        self.raw_log = Some(log);
+        self
+    }
+
+    pub fn register(&mut self, detector: impl Detector + 'static) {
+        self.detectors.push(Arc::new(detector));
+    }
+
+    /// Built-in detectors followed by the current file rules
+    pub fn detectors(&self) -> Vec<Arc<dyn Detector>> {
+        let mut detectors = self.detectors.clone();
+        if let Some(rules) = &self.rules {
+            detectors.extend(rules.detectors());
+        }
+        detectors
+    }
+
+    /// Probe `addr` and return the most confident detection, if any.
+    ///
+    /// Each distinct probe is sent once, in registration order, and its
+    /// response is matched by every detector that asked for it. Nothing more
+    /// is sent to a target that did not answer the first probe. The winning
+    /// detector then gets to confirm its match, which sets the final
+    /// confidence.
+    pub async fn run(&self, client: &Client, profile: &ProbeConfig, addr: SocketAddr, try_tls: bool, timeout: Duration) -> Option<Hit> {
+        let mut best: Option<(Hit, Arc<dyn Detector>)> = None;
+        for (index, (probe, detectors)) in self.probe_plan(profile).into_iter().enumerate() {
+            let observation = match observe(client, &probe, addr, try_tls, timeout).await {
+                Some(observation) => observation,
+                None if index == 0 => return None,
+                None => continue,
+            };
+
+            if let Some(log) = &self.raw_log {
+                if let Err(e) = log.append(&RawResponse::new(addr, &probe, &observation)) {
+                    eprintln!("Failed to save raw response from {}: {}", addr, e);
+                }
+            }
+
+            match_observation(&observation, detectors, &mut best);
+        }
+
+        let (mut hit, detector) = best?;
+        if let Some(confirmation) = detector.confirm(client, &hit.base_url, &hit.detection, timeout).await {
+            if confirmation.confidence == 0 {
+                return None;
+            }
+            hit.detection.confidence = confirmation.confidence;
+            hit.detection.attributes.extend(confirmation.attributes.clone());
+            hit.detection.confirmation = Some(confirmation);
+        }
+
+        Some(hit)
+    }
+
+    /// Match responses saved by an earlier scan of one target against the
+    /// current detectors, without sending anything.
+    ///
+    /// Each probe is matched against the latest response saved for it. A
+    /// probe of the profile's path with none falls back to the responses
+    /// saved for probes no longer planned, which an earlier profile sent,
+    /// preferring those of the same method. Confirmation probes are not
+    /// replayed, so the confidence is that of the initial match.
+    pub fn replay(&self, responses: &[RawResponse], profile: &ProbeConfig) -> Option<Hit> {
+        let plan = self.probe_plan(profile);
+        let stale: Vec<&RawResponse> = responses.iter()
+            .filter(|raw| plan.iter().all(|(probe, _)| raw.probe() != *probe))
+            .collect();
+
+        let mut best: Option<(Hit, Arc<dyn Detector>)> = None;
+        for (probe, detectors) in plan {
+            let saved = responses.iter().rev().find(|raw| raw.probe() == probe);
+            let raw = match saved {
+                Some(raw) => raw,
+                None if probe.path == profile.path => {
+                    match stale.iter().rev().find(|raw| raw.method == probe.method).or(stale.last()) {
+                        Some(raw) => raw,
+                        None => continue,
+                    }
+                }
+                None => continue,
+            };
+
+            match_observation(&Observation::from_raw(raw), detectors, &mut best);
+        }
+
+        best.map(|(hit, _)| hit)
+    }
+
+    /// Distinct probes, in registration order, each with the detectors that
+    /// asked for it
+    fn probe_plan(&self, profile: &ProbeConfig) -> Vec<(Probe, Vec<Arc<dyn Detector>>)> {
+        let mut probes: Vec<(Probe, Vec<Arc<dyn Detector>>)> = Vec::new();
+        for detector in self.detectors() {
+            for probe in detector.probes(profile) {
+                match probes.iter_mut().find(|(existing, _)| *existing == probe) {
+                    Some((_, detectors)) => detectors.push(detector.clone()),
+                    None => probes.push((probe, vec![detector.clone()])),
+                }
+            }
+        }
+        probes
+    }
+}
+
+impl Default for DetectorRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+/// Run `detectors` on `observation`, keeping the most confident hit in `best`
+fn match_observation(observation: &Observation, detectors: Vec<Arc<dyn Detector>>, best: &mut Option<(Hit, Arc<dyn Detector>)>) {
+    for detector in detectors {
+        let detection = match detector.detect(observation) {
+            Some(detection) => detection,
+            None => continue,
+        };
+        if best.as_ref().is_none_or(|(hit, _)| detection.confidence > hit.detection.confidence) {
+            let hit = Hit {
+                base_url: observation.base_url.clone(),
+                detection,
+                certificate: observation.certificate.clone(),
+                playlist_paths: detector.playlist_paths(),
+                response: observation.meta.clone(),
+            };
+            *best = Some((hit, detector));
+        }
+    }
+}
+
+/// Version following `product` in a header such as `Flussonic/23.03`
+fn product_version<'a>(value: &'a str, product: &str) -> Option<&'a str> {
+    let rest = &value[value.find(product)? + product.len()..];
+    let rest = rest.strip_prefix(['/', ' '])?;
+    let version = rest.split(|c: char| c.is_whitespace() || c == '(' || c == ';').next()?;
+    version.starts_with(|c: char| c.is_ascii_digit()).then_some(version)
+}
+
+/// Split `1.0.23-12` into version and build
+fn split_build(version: &str) -> (&str, Option<&str>) {
+    match version.split_once(['-', '+']) {
+        Some((version, build)) if !build.is_empty() => (version, Some(build)),
+        _ => (version, None),
+    }
+}
+
+/// Detection with product, version and build filled in from a version token
+fn product_detection(detector: &str, confidence: u8, product: &str, server: &str, version: Option<&str>) -> Detection {
+    let mut detection = Detection::new(detector, confidence)
+        .with_attribute("server", server)
+        .with_attribute(ATTR_PRODUCT, product);
+
+    if let Some(version) = version {
+        let (version, build) = split_build(version);
+        detection = detection.with_attribute(ATTR_VERSION, version);
+        if let Some(build) = build {
+            detection = detection.with_attribute(ATTR_BUILD, build);
+        }
+    }
+
+    detection
+}
+
+/// Fetch `url` and return the status, headers and start of the body
+async fn fetch_sample(client: &Client, url: &str, max_bytes: usize, timeout: Duration) -> Option<(StatusCode, HeaderMap, String)> {
+    let response = client.get(url).timeout(timeout).send().await.ok()?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = http::read_bounded(response, max_bytes, timeout).await
+        .map(|sample| String::from_utf8_lossy(&sample.bytes).into_owned())
+        .unwrap_or_default();
+    Some((status, headers, body))
+}
+
+/// Confirm a header match by requesting a path that cannot exist.
+///
+/// The real server answers 404 with its own `Server` header, containing
+/// `server_token`; catch-all honeypots answer with success.
+async fn confirm_missing_path(client: &Client, base_url: &str, server_token: &str, header_confidence: u8, timeout: Duration) -> Confirmation {
+    let missing_url = format!("{}/astra-scanner-{:08x}", base_url, rand::random::<u32>());
+    let response = match client.get(&missing_url).timeout(timeout).send().await {
+        Ok(response) => response,
+        Err(_) => return Confirmation::new(header_confidence / 2, "no answer to the confirmation probe"),
+    };
+
+    let same_server = response.headers().get(SERVER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|server| server.contains(server_token));
+
+    match response.status() {
+        StatusCode::NOT_FOUND if same_server => Confirmation::new(75, format!("missing path answered 404 by {}", server_token)),
+        StatusCode::NOT_FOUND => Confirmation::new(40, format!("missing path answered 404 without the {} header", server_token)),
+        status if status.is_success() => Confirmation::new(15, "missing path answered 200, likely a catch-all honeypot"),
+        status => Confirmation::new(header_confidence / 2, format!("missing path answered {}", status.as_u16())),
+    }
+}
+
+/// Send one probe and collect what came back
+async fn observe(client: &Client, probe: &Probe, addr: SocketAddr, try_tls: bool, timeout: Duration) -> Option<Observation> {
+    let (base_url, response, ttfb) = http::probe(client, probe.method, &probe.path, addr, try_tls, timeout).await?;
+
+    let status = response.status();
+    let headers = response.headers().clone();
+    let certificate = CertificateInfo::from_response(&response);
+    let mut meta = ResponseMeta::from_response(&response, ttfb);
+    let body = match http::read_bounded(response, BODY_SAMPLE_BYTES, timeout).await {
+        Ok(sample) => {
+            meta.latency_ms = (ttfb + sample.elapsed).as_millis() as u64;
+            sample.bytes
+        }
+        Err(_) => Vec::new(),
+    };
+
+    Some(Observation {
+        base_url,
+        status,
+        headers,
+        body,
+        certificate,
+        meta,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+
+    /// Saved answer of a server with the given `Server` header to `probe`
+    fn raw(probe: &Probe, server: &str) -> RawResponse {
+        RawResponse {
+            addr: "10.0.0.1:8000".parse().unwrap(),
+            captured_at: Local::now(),
+            method: probe.method,
+            path: probe.path.clone(),
+            base_url: "http://10.0.0.1:8000".to_string(),
+            status: 200,
+            meta: ResponseMeta {
+                url: format!("http://10.0.0.1:8000{}", probe.path),
+                status_line: "HTTP/1.1 200 OK".to_string(),
+                headers: vec![("server".to_string(), server.to_string())],
+                content_type: None,
+                content_length: None,
+                ttfb_ms: 0,
+                latency_ms: 0,
+            },
+            body: String::new(),
+            certificate: None,
+        }
+    }
+
+    fn profile(method: ProbeMethod, path: &str) -> ProbeConfig {
+        ProbeConfig {
+            method,
+            path: path.to_string(),
+            ..ProbeConfig::default()
+        }
+    }
+
+    #[test]
+    fn replay_matches_the_planned_probe() {
+        let current = profile(ProbeMethod::Get, "/");
+        let responses = [raw(&Probe::from_profile(&current), "Astra/5.64")];
+
+        let hit = DetectorRegistry::builtin().replay(&responses, &current).unwrap();
+        assert_eq!(hit.detection.detector, "astra");
+    }
+
+    #[test]
+    fn replay_reads_responses_to_an_earlier_profile() {
+        let earlier = profile(ProbeMethod::Head, "/");
+        let responses = [raw(&Probe::from_profile(&earlier), "Astra/5.64")];
+
+        let current = profile(ProbeMethod::Get, "/status");
+        let hit = DetectorRegistry::builtin().replay(&responses, &current).unwrap();
+        assert_eq!(hit.detection.detector, "astra");
+    }
+
+    #[test]
+    fn replay_prefers_the_response_to_the_current_profile() {
+        let earlier = profile(ProbeMethod::Get, "/old");
+        let current = profile(ProbeMethod::Get, "/");
+        let responses = [
+            raw(&Probe::from_profile(&current), "Astra/5.64"),
+            raw(&Probe::from_profile(&earlier), "nginx/1.18.0"),
+        ];
+
+        let hit = DetectorRegistry::builtin().replay(&responses, &current).unwrap();
+        assert_eq!(hit.detection.detector, "astra");
+    }
+
+    #[test]
+    fn replay_without_responses_matches_nothing() {
+        assert!(DetectorRegistry::builtin().replay(&[], &ProbeConfig::default()).is_none());
+    }
+}
@@ -0,0 +1,112 @@
+use std::time::Duration;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::{Client, Url};
+
+use super::{fetch_sample, product_version, Confirmation, Detection, Detector, Observation, ATTR_PRODUCT, ATTR_VERSION};
+
+/// An RTMP hint next to an `nginx` header is not proof, so the match only
+/// counts once the statistics page is found
+const HEADER_CONFIDENCE: u8 = 30;
+
+/// Port the RTMP module listens on by default
+const RTMP_PORT: u16 = 1935;
+
+/// Body markers of a page served by, or linking to, the RTMP module: the
+/// statistics page's stylesheet, or any mention of RTMP
+const RTMP_MARKERS: &[&str] = &["stat.xsl", "rtmp"];
+
+/// Bytes of the statistics page read to confirm a match
+const STAT_SAMPLE_BYTES: usize = 16 * 1024;
+
+lazy_static! {
+    static ref RTMP_VERSION: Regex = Regex::new(r"<nginx_rtmp_version>\s*([^<\s]+)").unwrap();
+}
+
+/// nginx with the RTMP module, confirmed through the module's XML
+/// statistics page at `/stat`.
+///
+/// Only nginx servers showing an RTMP signal (the RTMP port, or an RTMP
+/// marker in the headers or body) are candidates, so plain nginx web servers
+/// are not sent the extra probe. Candidates without a public `/stat` are
+/// rejected rather than recorded. Streams are published per application, so
+/// there is no playlist.
+pub struct NginxRtmpDetector;
+
+#[async_trait]
+impl Detector for NginxRtmpDetector {
+    fn name(&self) -> &str {
+        "nginx-rtmp"
+    }
+
+    fn detect(&self, observation: &Observation) -> Option<Detection> {
+        let server = observation.header("server")?;
+        if !server.starts_with("nginx") {
+            return None;
+        }
+        let signal = rtmp_signal(&observation.base_url, server, &observation.body)?;
+
+        let mut detection = Detection::new(self.name(), HEADER_CONFIDENCE)
+            .with_attribute("server", server)
+            .with_attribute(ATTR_PRODUCT, "nginx-rtmp")
+            .with_attribute("rtmp_signal", signal);
+        if let Some(version) = product_version(server, "nginx") {
+            detection = detection.with_attribute("nginx_version", version);
+        }
+
+        Some(detection)
+    }
+
+    async fn confirm(&self, client: &Client, base_url: &str, _detection: &Detection, timeout: Duration) -> Option<Confirmation> {
+        let stat_url = format!("{}/stat", base_url);
+        let body = match fetch_sample(client, &stat_url, STAT_SAMPLE_BYTES, timeout).await {
+            Some((status, _, body)) if status.is_success() => body,
+            _ => return Some(Confirmation::new(0, "no public /stat page")),
+        };
+
+        if !body.contains("<rtmp>") {
+            return Some(Confirmation::new(0, "/stat is not the RTMP statistics page"));
+        }
+
+        let mut confirmation = Confirmation::new(90, "/stat is the RTMP statistics page");
+        if let Some(version) = RTMP_VERSION.captures(&body).and_then(|captures| captures.get(1)) {
+            confirmation = confirmation.with_attribute(ATTR_VERSION, version.as_str());
+        }
+        Some(confirmation)
+    }
+}
+
+/// What makes an nginx response look like the RTMP module, if anything
+fn rtmp_signal(base_url: &str, server: &str, body: &[u8]) -> Option<&'static str> {
+    if Url::parse(base_url).ok().and_then(|url| url.port()) == Some(RTMP_PORT) {
+        return Some("port");
+    }
+    if server.to_ascii_lowercase().contains("rtmp") {
+        return Some("server header");
+    }
+    let body = String::from_utf8_lossy(body).to_ascii_lowercase();
+    RTMP_MARKERS.iter()
+        .any(|marker| body.contains(marker))
+        .then_some("body")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rtmp_signals() {
+        assert_eq!(rtmp_signal("http://10.0.0.1:1935", "nginx", b""), Some("port"));
+        assert_eq!(rtmp_signal("http://10.0.0.1:8080", "nginx-rtmp/1.2.2", b""), Some("server header"));
+        let stat = b"<?xml-stylesheet type=\"text/xsl\" href=\"stat.xsl\" ?>\n<rtmp>";
+        assert_eq!(rtmp_signal("http://10.0.0.1:8080", "nginx/1.18.0", stat), Some("body"));
+        assert_eq!(rtmp_signal("http://10.0.0.1", "nginx", b"<a href=\"RTMP://host/live\">"), Some("body"));
+    }
+
+    #[test]
+    fn plain_nginx_has_no_rtmp_signal() {
+        assert_eq!(rtmp_signal("http://10.0.0.1", "nginx/1.18.0", b"<h1>Welcome to nginx!</h1>"), None);
+        assert_eq!(rtmp_signal("https://10.0.0.1:8443", "nginx", b""), None);
+    }
+}
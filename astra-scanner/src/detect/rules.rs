@@ -0,0 +1,463 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use anyhow::{anyhow, bail, Context, Result};
+use regex::{Regex, RegexBuilder};
+use reqwest::header::HeaderName;
+use serde::Deserialize;
+
+use super::{Detection, Detector, DetectorRegistry, Observation, Probe, ATTR_PRODUCT};
+use crate::config::{ProbeConfig, ProbeMethod};
+
+/// Fingerprint rules shipped next to `pool/config.json`
+pub const FINGERPRINTS_PATH: &str = "pool/fingerprints.json";
+
+/// How often the rules file is checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    #[serde(default)]
+    rules: Vec<RuleSpec>,
+}
+
+/// One rule as written in the file. Every condition given must match.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    name: String,
+    /// Product reported when the rule matches, unless captured
+    product: Option<String>,
+    #[serde(default = "default_confidence")]
+    confidence: u8,
+    /// Request to send; defaults to the probe profile's method, or `GET`
+    /// when there is a body condition
+    method: Option<ProbeMethod>,
+    path: Option<String>,
+    /// Accepted status codes, any if empty
+    #[serde(default)]
+    status: Vec<u16>,
+    /// Regex on the `Content-Type` header
+    content_type: Option<String>,
+    /// Header name to regex on its value
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    /// Regex on the start of the body
+    body: Option<String>,
+}
+
+fn default_confidence() -> u8 {
+    70
+}
+
+/// Detector compiled from a rule in the fingerprints file.
+///
+/// Named capture groups in the header and body regexes become detection
+/// attributes, so `(?P<version>...)` fills in the server's version.
+#[derive(Debug)]
+pub struct RuleDetector {
+    name: String,
+    product: Option<String>,
+    confidence: u8,
+    method: Option<ProbeMethod>,
+    path: Option<String>,
+    status: Vec<u16>,
+    content_type: Option<Regex>,
+    headers: Vec<(HeaderName, Regex)>,
+    body: Option<Regex>,
+}
+
+impl RuleDetector {
+    fn compile(spec: RuleSpec) -> Result<Self> {
+        if spec.name.trim().is_empty() {
+            bail!("rule without a name");
+        }
+        if spec.confidence > 100 {
+            bail!("confidence {} is above 100", spec.confidence);
+        }
+        if spec.status.is_empty() && spec.content_type.is_none() && spec.headers.is_empty() && spec.body.is_none() {
+            bail!("no status, content_type, headers or body condition");
+        }
+        if let Some(code) = spec.status.iter().find(|code| !(100..600).contains(*code)) {
+            bail!("invalid status code {}", code);
+        }
+
+        let headers = spec.headers.iter()
+            .map(|(name, pattern)| {
+                let name = HeaderName::from_bytes(name.as_bytes())
+                    .with_context(|| format!("invalid header name '{}'", name))?;
+                Ok((name, compile_regex(pattern)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            name: spec.name,
+            product: spec.product,
+            confidence: spec.confidence,
+            method: spec.method,
+            path: spec.path,
+            status: spec.status,
+            content_type: spec.content_type.as_deref().map(compile_regex).transpose()?,
+            headers,
+            body: spec.body.as_deref().map(compile_regex).transpose()?,
+        })
+    }
+}
+
+fn compile_regex(pattern: &str) -> Result<Regex> {
+    RegexBuilder::new(pattern)
+        .size_limit(1 << 20)
+        .build()
+        .with_context(|| format!("invalid regex '{}'", pattern))
+}
+
+/// Add the named groups of a match to `detection`
+fn capture_into(regex: &Regex, haystack: &str, detection: &mut Detection) -> bool {
+    let captures = match regex.captures(haystack) {
+        Some(captures) => captures,
+        None => return false,
+    };
+
+    for name in regex.capture_names().flatten() {
+        if let Some(value) = captures.name(name) {
+            detection.attributes.insert(name.to_string(), value.as_str().to_string());
+        }
+    }
+
+    true
+}
+
+impl Detector for RuleDetector {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn probes(&self, profile: &ProbeConfig) -> Vec<Probe> {
+        let mut probe = Probe::from_profile(profile);
+        if let Some(path) = &self.path {
+            probe.path = path.clone();
+        }
+        // Body conditions need a body, which HEAD would not return
+        if self.body.is_some() {
+            probe.method = ProbeMethod::Get;
+        }
+        if let Some(method) = self.method {
+            probe.method = method;
+        }
+        vec![probe]
+    }
+
+    fn detect(&self, observation: &Observation) -> Option<Detection> {
+        if !self.status.is_empty() && !self.status.contains(&observation.status.as_u16()) {
+            return None;
+        }
+
+        let mut detection = Detection::new(&self.name, self.confidence);
+        if let Some(product) = &self.product {
+            detection = detection.with_attribute(ATTR_PRODUCT, product.as_str());
+        }
+
+        if let Some(content_type) = &self.content_type {
+            if !content_type.is_match(observation.header("content-type")?) {
+                return None;
+            }
+        }
+
+        for (name, regex) in &self.headers {
+            let value = observation.headers.get(name)?.to_str().ok()?;
+            if !capture_into(regex, value, &mut detection) {
+                return None;
+            }
+        }
+
+        if let Some(body) = &self.body {
+            if !capture_into(body, &String::from_utf8_lossy(&observation.body), &mut detection) {
+                return None;
+            }
+        }
+
+        Some(detection)
+    }
+}
+
+/// Parse and validate a fingerprints file.
+///
+/// Fails on the first invalid rule, naming it, so a bad update is never
+/// half applied. Rule names must be unique and must not reuse a built-in
+/// detector's name.
+pub fn parse_rules(content: &str) -> Result<Vec<RuleDetector>> {
+    let file: RuleFile = serde_json::from_str(content)
+        .with_context(|| "Failed to parse fingerprint rules")?;
+
+    let builtin: HashSet<String> = DetectorRegistry::builtin().detectors().iter()
+        .map(|detector| detector.name().to_string())
+        .collect();
+    let mut names = HashSet::new();
+    file.rules.into_iter()
+        .enumerate()
+        .map(|(index, spec)| {
+            let label = if spec.name.is_empty() { format!("#{}", index + 1) } else { spec.name.clone() };
+            if !names.insert(spec.name.clone()) {
+                return Err(anyhow!("Fingerprint rule '{}': duplicate name", label));
+            }
+            if builtin.contains(&spec.name) {
+                return Err(anyhow!("Fingerprint rule '{}': name of a built-in detector", label));
+            }
+            RuleDetector::compile(spec).with_context(|| format!("Fingerprint rule '{}'", label))
+        })
+        .collect()
+}
+
+/// Rules loaded from a file and reloaded when it changes
+pub struct RuleSet {
+    path: PathBuf,
+    loaded: RwLock<LoadedRules>,
+    last_check: Mutex<Instant>,
+}
+
+struct LoadedRules {
+    modified: Option<SystemTime>,
+    detectors: Vec<Arc<dyn Detector>>,
+}
+
+impl RuleSet {
+    /// Load rules from `path`; a missing file means no rules
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let loaded = read_rules(&path)?;
+
+        Ok(Self {
+            path,
+            loaded: RwLock::new(loaded),
+            last_check: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// Current rules, picking up changes to the file.
+    ///
+    /// An invalid update is reported and the previous rules stay in use.
+    pub fn detectors(&self) -> Vec<Arc<dyn Detector>> {
+        if self.should_check() {
+            let modified = modified_time(&self.path);
+            let changed = self.loaded.read().map(|loaded| loaded.modified != modified).unwrap_or(false);
+
+            if changed {
+                match read_rules(&self.path) {
+                    Ok(rules) => {
+                        println!("Reloaded {} fingerprint rules from {}", rules.detectors.len(), self.path.display());
+                        if let Ok(mut loaded) = self.loaded.write() {
+                            *loaded = rules;
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Keeping previous fingerprint rules: {:#}", e);
+                        // Don't retry until the file changes again
+                        if let Ok(mut loaded) = self.loaded.write() {
+                            loaded.modified = modified;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.loaded.read()
+            .map(|loaded| loaded.detectors.clone())
+            .unwrap_or_default()
+    }
+
+    fn should_check(&self) -> bool {
+        match self.last_check.lock() {
+            Ok(mut last_check) if last_check.elapsed() >= RELOAD_INTERVAL => {
+                *last_check = Instant::now();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn read_rules(path: &Path) -> Result<LoadedRules> {
+    let modified = modified_time(path);
+    if !path.exists() {
+        return Ok(LoadedRules { modified, detectors: Vec::new() });
+    }
+
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let detectors = parse_rules(&content)
+        .with_context(|| format!("Invalid {}", path.display()))?
+        .into_iter()
+        .map(|rule| Arc::new(rule) as Arc<dyn Detector>)
+        .collect();
+
+    Ok(LoadedRules { modified, detectors })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use reqwest::StatusCode;
+    use crate::config::ProbeConfig;
+    use crate::detect::ATTR_VERSION;
+    use crate::http::ResponseMeta;
+
+    /// Response to a probe with the given status, headers and body
+    fn observation(status: u16, headers: &[(&str, &str)], body: &str) -> Observation {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(HeaderName::from_bytes(name.as_bytes()).unwrap(), HeaderValue::from_str(value).unwrap());
+        }
+        Observation {
+            base_url: "http://10.0.0.1:8000".to_string(),
+            status: StatusCode::from_u16(status).unwrap(),
+            headers: map,
+            body: body.as_bytes().to_vec(),
+            certificate: None,
+            meta: ResponseMeta {
+                url: "http://10.0.0.1:8000/".to_string(),
+                status_line: format!("HTTP/1.1 {}", status),
+                headers: headers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+                content_type: None,
+                content_length: None,
+                ttfb_ms: 0,
+                latency_ms: 0,
+            },
+        }
+    }
+
+    /// The single rule in `json`
+    fn rule(json: &str) -> RuleDetector {
+        let mut rules = parse_rules(&format!(r#"{{ "rules": [{}] }}"#, json)).unwrap();
+        assert_eq!(rules.len(), 1);
+        rules.remove(0)
+    }
+
+    #[test]
+    fn header_rule_captures_the_version() {
+        let nimble = rule(r#"{
+            "name": "nimble",
+            "product": "Nimble Streamer",
+            "confidence": 80,
+            "headers": { "server": "^Nimble(?:/(?P<version>[0-9.]+))?" }
+        }"#);
+
+        let detection = nimble.detect(&observation(200, &[("server", "Nimble/3.7.5")], "")).unwrap();
+        assert_eq!(detection.detector, "nimble");
+        assert_eq!(detection.confidence, 80);
+        assert_eq!(detection.attributes.get(ATTR_PRODUCT).map(String::as_str), Some("Nimble Streamer"));
+        assert_eq!(detection.attributes.get(ATTR_VERSION).map(String::as_str), Some("3.7.5"));
+
+        let bare = nimble.detect(&observation(200, &[("server", "Nimble")], "")).unwrap();
+        assert_eq!(bare.attributes.get(ATTR_VERSION), None);
+
+        assert!(nimble.detect(&observation(200, &[("server", "nginx")], "")).is_none());
+        assert!(nimble.detect(&observation(200, &[], "")).is_none());
+    }
+
+    #[test]
+    fn every_condition_must_match() {
+        let panel = rule(r#"{
+            "name": "xui",
+            "product": "XUI",
+            "status": [200],
+            "content_type": "^text/html",
+            "body": "<title>(?P<product>XUI[^<]*)</title>"
+        }"#);
+        let html = [("content-type", "text/html; charset=utf-8")];
+        let page = "<html><title>XUI.one</title></html>";
+
+        // A captured product replaces the rule's
+        let detection = panel.detect(&observation(200, &html, page)).unwrap();
+        assert_eq!(detection.attributes.get(ATTR_PRODUCT).map(String::as_str), Some("XUI.one"));
+
+        assert!(panel.detect(&observation(404, &html, page)).is_none());
+        assert!(panel.detect(&observation(200, &[("content-type", "application/json")], page)).is_none());
+        assert!(panel.detect(&observation(200, &[], page)).is_none());
+        assert!(panel.detect(&observation(200, &html, "<title>Login</title>")).is_none());
+    }
+
+    #[test]
+    fn probes_follow_the_rule() {
+        let profile = ProbeConfig {
+            method: ProbeMethod::Head,
+            path: "/".to_string(),
+            ..ProbeConfig::default()
+        };
+
+        let header_rule = rule(r#"{ "name": "a", "headers": { "server": "^A" } }"#);
+        assert_eq!(header_rule.probes(&profile), vec![Probe::new(ProbeMethod::Head, "/")]);
+
+        let body_rule = rule(r#"{ "name": "b", "path": "/status", "body": "ok" }"#);
+        assert_eq!(body_rule.probes(&profile), vec![Probe::new(ProbeMethod::Get, "/status")]);
+
+        let head_rule = rule(r#"{ "name": "c", "method": "HEAD", "body": "ok" }"#);
+        assert_eq!(head_rule.probes(&profile), vec![Probe::new(ProbeMethod::Head, "/")]);
+    }
+
+    #[test]
+    fn rule_set_reloads_a_changed_file() {
+        let path = std::env::temp_dir().join(format!("astra-scanner-fingerprints-{}.json", std::process::id()));
+        let names = |set: &RuleSet| -> Vec<String> {
+            set.detectors().iter().map(|detector| detector.name().to_string()).collect()
+        };
+        // Rewrite the file with a later modification time, and let the next
+        // call check it without waiting for the reload interval
+        let update = |set: &RuleSet, content: &str, age: u64| {
+            fs::write(&path, content).unwrap();
+            let file = fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(SystemTime::now() + Duration::from_secs(age)).unwrap();
+            *set.last_check.lock().unwrap() = Instant::now() - RELOAD_INTERVAL;
+        };
+
+        fs::write(&path, rules(&["nimble"])).unwrap();
+        let set = RuleSet::load(&path).unwrap();
+        assert_eq!(names(&set), ["nimble"]);
+
+        // Unchanged until the interval has passed
+        fs::write(&path, rules(&["nimble", "xui"])).unwrap();
+        assert_eq!(names(&set), ["nimble"]);
+
+        update(&set, &rules(&["nimble", "xui"]), 10);
+        assert_eq!(names(&set), ["nimble", "xui"]);
+
+        // An invalid update keeps the previous rules
+        update(&set, &rules(&["astra"]), 20);
+        assert_eq!(names(&set), ["nimble", "xui"]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn rules(names: &[&str]) -> String {
+        let rules: Vec<_> = names.iter()
+            .map(|name| format!(r#"{{ "name": "{}", "headers": {{ "server": "^Nimble" }} }}"#, name))
+            .collect();
+        format!(r#"{{ "rules": [{}] }}"#, rules.join(", "))
+    }
+
+    #[test]
+    fn distinct_names_are_accepted() {
+        assert_eq!(parse_rules(&rules(&["nimble", "xui"])).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn duplicate_names_are_rejected() {
+        let error = parse_rules(&rules(&["nimble", "nimble"])).unwrap_err();
+        assert!(error.to_string().contains("duplicate name"));
+    }
+
+    #[test]
+    fn builtin_detector_names_are_rejected() {
+        for name in ["astra", "flussonic", "nginx-rtmp"] {
+            let error = parse_rules(&rules(&[name])).unwrap_err();
+            assert!(error.to_string().contains("built-in detector"), "{}: {}", name, error);
+        }
+    }
+}
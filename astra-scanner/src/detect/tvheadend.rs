@@ -0,0 +1,66 @@
+use std::time::Duration;
+use async_trait::async_trait;
+use reqwest::header::WWW_AUTHENTICATE;
+use reqwest::Client;
+
+use crate::playlist::Protection;
+
+use super::{confirm_missing_path, fetch_sample, product_detection, Confirmation, Detection, Detector, Observation};
+
+/// Confidence from the `Server` header alone
+const HEADER_CONFIDENCE: u8 = 60;
+
+/// Bytes of the playlist read to check that it is one
+const PLAYLIST_SAMPLE_BYTES: usize = 4096;
+
+const PLAYLIST_PATH: &str = "/playlist/channels.m3u";
+
+/// Playlist paths in order of preference; older releases have no `.m3u` suffix
+const PLAYLIST_PATHS: [&str; 3] = [PLAYLIST_PATH, "/playlist/channels", "/playlist"];
+
+/// TVHeadend, identified by its `Server` header (`HTS/tvheadend`).
+///
+/// The channel playlist confirms the match; when it is behind a login, the
+/// `tvheadend` realm of the challenge does.
+pub struct TvheadendDetector;
+
+#[async_trait]
+impl Detector for TvheadendDetector {
+    fn name(&self) -> &str {
+        "tvheadend"
+    }
+
+    fn detect(&self, observation: &Observation) -> Option<Detection> {
+        let server = observation.header("server")?;
+        if !server.to_lowercase().contains("tvheadend") {
+            return None;
+        }
+
+        Some(product_detection(self.name(), HEADER_CONFIDENCE, "TVHeadend", server, None))
+    }
+
+    fn playlist_paths(&self) -> Vec<String> {
+        PLAYLIST_PATHS.iter().map(|path| path.to_string()).collect()
+    }
+
+    async fn confirm(&self, client: &Client, base_url: &str, _detection: &Detection, timeout: Duration) -> Option<Confirmation> {
+        let playlist_url = format!("{}{}", base_url, PLAYLIST_PATH);
+        let mut protection = None;
+        if let Some((status, headers, body)) = fetch_sample(client, &playlist_url, PLAYLIST_SAMPLE_BYTES, timeout).await {
+            if status.is_success() && body.contains("#EXTM3U") {
+                return Some(Confirmation::new(95, "channel playlist is an M3U playlist"));
+            }
+            protection = Protection::of_response(PLAYLIST_PATH, status, &headers);
+
+            let tvheadend_realm = headers.get(WWW_AUTHENTICATE)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|challenge| challenge.to_lowercase().contains("tvheadend"));
+            if tvheadend_realm {
+                return Some(Confirmation::new(85, "channel playlist asks for tvheadend credentials").with_protection(protection));
+            }
+        }
+
+        let confirmation = confirm_missing_path(client, base_url, "tvheadend", HEADER_CONFIDENCE, timeout).await;
+        Some(confirmation.with_protection(protection))
+    }
+}
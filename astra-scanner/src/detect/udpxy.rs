@@ -0,0 +1,44 @@
+use std::time::Duration;
+use async_trait::async_trait;
+use reqwest::Client;
+
+use super::{confirm_missing_path, fetch_sample, product_detection, product_version, Confirmation, Detection, Detector, Observation};
+
+/// Confidence from the `Server` header alone
+const HEADER_CONFIDENCE: u8 = 60;
+
+/// Bytes of the status page read to confirm a match
+const STATUS_SAMPLE_BYTES: usize = 8192;
+
+/// udpxy multicast-to-HTTP relay, identified by its `Server` header
+/// (`udpxy 1.0.23-12`) and confirmed through its public `/status` page.
+///
+/// udpxy relays `/udp/<group>:<port>` but serves no playlist of its own.
+pub struct UdpxyDetector;
+
+#[async_trait]
+impl Detector for UdpxyDetector {
+    fn name(&self) -> &str {
+        "udpxy"
+    }
+
+    fn detect(&self, observation: &Observation) -> Option<Detection> {
+        let server = observation.header("server")?;
+        if !server.contains("udpxy") {
+            return None;
+        }
+
+        Some(product_detection(self.name(), HEADER_CONFIDENCE, "udpxy", server, product_version(server, "udpxy")))
+    }
+
+    async fn confirm(&self, client: &Client, base_url: &str, _detection: &Detection, timeout: Duration) -> Option<Confirmation> {
+        let status_url = format!("{}/status", base_url);
+        if let Some((status, _, body)) = fetch_sample(client, &status_url, STATUS_SAMPLE_BYTES, timeout).await {
+            if status.is_success() && body.contains("udpxy") {
+                return Some(Confirmation::new(95, "/status is the udpxy status page"));
+            }
+        }
+
+        Some(confirm_missing_path(client, base_url, "udpxy", HEADER_CONFIDENCE, timeout).await)
+    }
+}
@@ -0,0 +1,64 @@
+use std::time::Duration;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::Client;
+
+use super::{confirm_missing_path, fetch_sample, product_detection, product_version, Confirmation, Detection, Detector, Observation, ATTR_BUILD, ATTR_EDITION, ATTR_VERSION};
+
+/// Confidence from the `Server` header alone
+const HEADER_CONFIDENCE: u8 = 60;
+
+/// Bytes of the landing page read to confirm a match
+const PAGE_SAMPLE_BYTES: usize = 8192;
+
+lazy_static! {
+    // e.g. `Wowza Streaming Engine 4 Perpetual Edition 4.7.5.01 build21752`
+    static ref BANNER: Regex = Regex::new(
+        r"Wowza (?:Streaming|Media) (?:Engine|Server) \d+ (?P<edition>[\w ]+?) Edition (?P<version>[\d.]+)(?: build\s*(?P<build>\d+))?"
+    ).unwrap();
+}
+
+/// Wowza Streaming Engine, identified by its `Server` header
+/// (`WowzaStreamingEngine/4.8.10`) and confirmed by the banner on its
+/// landing page, which also gives the edition and build.
+///
+/// Playlists are per application and stream, so none is fetched.
+pub struct WowzaDetector;
+
+#[async_trait]
+impl Detector for WowzaDetector {
+    fn name(&self) -> &str {
+        "wowza"
+    }
+
+    fn detect(&self, observation: &Observation) -> Option<Detection> {
+        let server = observation.header("server")?;
+        if !server.contains("Wowza") {
+            return None;
+        }
+
+        let version = product_version(server, "WowzaStreamingEngine")
+            .or_else(|| product_version(server, "WowzaMediaServer"));
+        Some(product_detection(self.name(), HEADER_CONFIDENCE, "Wowza Streaming Engine", server, version))
+    }
+
+    async fn confirm(&self, client: &Client, base_url: &str, _detection: &Detection, timeout: Duration) -> Option<Confirmation> {
+        let page_url = format!("{}/", base_url);
+        if let Some((status, _, body)) = fetch_sample(client, &page_url, PAGE_SAMPLE_BYTES, timeout).await {
+            if status.is_success() {
+                if let Some(banner) = BANNER.captures(&body) {
+                    let mut confirmation = Confirmation::new(95, "landing page shows the Wowza banner");
+                    for (group, key) in [("edition", ATTR_EDITION), ("version", ATTR_VERSION), ("build", ATTR_BUILD)] {
+                        if let Some(value) = banner.name(group) {
+                            confirmation = confirmation.with_attribute(key, value.as_str());
+                        }
+                    }
+                    return Some(confirmation);
+                }
+            }
+        }
+
+        Some(confirm_missing_path(client, base_url, "Wowza", HEADER_CONFIDENCE, timeout).await)
+    }
+}
@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::catalog::ChannelGroup;
+use crate::channel::{self, Channel};
+
+/// A channel of an XMLTV guide
+#[derive(Debug, Clone)]
+pub struct GuideChannel {
+    pub id: String,
+    pub display_names: Vec<String>,
+}
+
+/// A programme of an XMLTV guide
+#[derive(Debug, Clone)]
+pub struct Programme {
+    /// Id of the guide channel it airs on
+    pub channel: String,
+    pub start: Option<DateTime<FixedOffset>>,
+    pub stop: Option<DateTime<FixedOffset>>,
+    /// First title listed
+    pub title: String,
+}
+
+/// How a channel was matched to a guide channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    /// Its `tvg-id` is the guide channel's id
+    TvgId,
+    /// Its name normalizes to one of the guide channel's display names
+    Name,
+}
+
+/// An XMLTV guide, indexed for matching channels against it
+#[derive(Debug, Default)]
+pub struct Guide {
+    pub channels: Vec<GuideChannel>,
+    /// Programmes by guide channel id, in start order
+    programmes: HashMap<String, Vec<Programme>>,
+    /// Position in `channels` by lowercased id
+    by_id: HashMap<String, usize>,
+    /// Position in `channels` by normalized display name
+    by_name: HashMap<String, usize>,
+}
+
+impl Guide {
+    /// Read an XMLTV file
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open guide {:?}", path))?;
+        Self::parse(BufReader::new(file)).with_context(|| format!("Failed to read guide {:?}", path))
+    }
+
+    /// Parse XMLTV. Elements other than `channel` and `programme` are
+    /// skipped, and so are the details of those besides names, times and
+    /// the first title.
+    pub fn parse<R: BufRead>(input: R) -> Result<Self> {
+        let mut reader = Reader::from_reader(input);
+        reader.config_mut().trim_text(true);
+
+        let mut guide = Guide::default();
+        let mut channel: Option<GuideChannel> = None;
+        let mut programme: Option<Programme> = None;
+        let mut in_text = false;
+        let mut buf = Vec::new();
+
+        loop {
+            let event = reader.read_event_into(&mut buf)
+                .with_context(|| format!("Invalid XML at byte {}", reader.buffer_position()))?;
+
+            match event {
+                Event::Start(element) => match element.local_name().as_ref() {
+                    b"channel" => channel = Some(guide_channel(&element)?),
+                    b"programme" => programme = Some(new_programme(&element)?),
+                    b"display-name" => in_text = channel.is_some(),
+                    b"title" => in_text = programme.as_ref().is_some_and(|programme| programme.title.is_empty()),
+                    _ => {}
+                },
+                Event::Empty(element) if element.local_name().as_ref() == b"channel" => {
+                    guide.add_channel(guide_channel(&element)?);
+                }
+                Event::Text(text) if in_text => {
+                    let text = text.unescape()?.into_owned();
+                    if let Some(channel) = channel.as_mut() {
+                        channel.display_names.push(text);
+                    } else if let Some(programme) = programme.as_mut() {
+                        programme.title = text;
+                    }
+                }
+                Event::CData(text) if in_text => {
+                    let text = String::from_utf8_lossy(&text.into_inner()).into_owned();
+                    if let Some(channel) = channel.as_mut() {
+                        channel.display_names.push(text);
+                    } else if let Some(programme) = programme.as_mut() {
+                        programme.title = text;
+                    }
+                }
+                Event::End(element) => match element.local_name().as_ref() {
+                    b"channel" => {
+                        if let Some(channel) = channel.take() {
+                            guide.add_channel(channel);
+                        }
+                    }
+                    b"programme" => {
+                        if let Some(programme) = programme.take() {
+                            guide.programmes.entry(programme.channel.clone()).or_default().push(programme);
+                        }
+                    }
+                    b"display-name" | b"title" => in_text = false,
+                    _ => {}
+                },
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        for programmes in guide.programmes.values_mut() {
+            programmes.sort_by_key(|programme| programme.start);
+        }
+
+        Ok(guide)
+    }
+
+    fn add_channel(&mut self, channel: GuideChannel) {
+        let position = self.channels.len();
+        self.by_id.entry(channel.id.to_lowercase()).or_insert(position);
+        for name in &channel.display_names {
+            self.by_name.entry(channel::normalize_name(name)).or_insert(position);
+        }
+        self.channels.push(channel);
+    }
+
+    /// Guide channel of `channel`: the one whose id is its `tvg-id`, else
+    /// one with a display name that normalizes like its `tvg-name` or name
+    pub fn find(&self, channel: &Channel) -> Option<(&GuideChannel, MatchKind)> {
+        let by_id = channel.tvg_id.as_deref()
+            .map(str::trim)
+            .filter(|tvg_id| !tvg_id.is_empty())
+            .and_then(|tvg_id| self.by_id.get(&tvg_id.to_lowercase()));
+        if let Some(&position) = by_id {
+            return Some((&self.channels[position], MatchKind::TvgId));
+        }
+
+        channel.attributes.get("tvg-name").into_iter()
+            .chain([channel.name.as_str()])
+            .find_map(|name| self.by_name.get(&channel::normalize_name(name)))
+            .map(|&position| (&self.channels[position], MatchKind::Name))
+    }
+
+    /// Programmes of a guide channel, in start order
+    pub fn programmes(&self, channel_id: &str) -> &[Programme] {
+        self.programmes.get(channel_id).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Programmes of a guide channel that have not ended by `now`, starting
+    /// with the one on air
+    pub fn upcoming(&self, channel_id: &str, now: DateTime<Utc>) -> impl Iterator<Item = &Programme> {
+        self.programmes(channel_id).iter()
+            .filter(move |programme| programme.stop.or(programme.start).is_none_or(|end| end > now))
+    }
+
+    pub fn programme_count(&self) -> usize {
+        self.programmes.values().map(Vec::len).sum()
+    }
+}
+
+/// How many logical channels of a catalog the guide covers
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Coverage {
+    pub channels: usize,
+    pub by_tvg_id: usize,
+    pub by_name: usize,
+}
+
+impl Coverage {
+    pub fn matched(&self) -> usize {
+        self.by_tvg_id + self.by_name
+    }
+
+    pub fn percent(&self) -> f64 {
+        if self.channels == 0 {
+            return 0.0;
+        }
+        self.matched() as f64 * 100.0 / self.channels as f64
+    }
+}
+
+/// Guide coverage of catalog groups. A group counts as matched by `tvg-id`
+/// if any source is, else by name if any source is.
+pub fn coverage(guide: &Guide, groups: &[ChannelGroup]) -> Coverage {
+    let mut coverage = Coverage {
+        channels: groups.len(),
+        ..Coverage::default()
+    };
+
+    for group in groups {
+        let kinds: Vec<MatchKind> = group.sources.iter()
+            .filter_map(|source| guide.find(source).map(|(_, kind)| kind))
+            .collect();
+        if kinds.contains(&MatchKind::TvgId) {
+            coverage.by_tvg_id += 1;
+        } else if !kinds.is_empty() {
+            coverage.by_name += 1;
+        }
+    }
+
+    coverage
+}
+
+fn guide_channel(element: &BytesStart) -> Result<GuideChannel> {
+    Ok(GuideChannel {
+        id: attribute(element, b"id")?.unwrap_or_default(),
+        display_names: Vec::new(),
+    })
+}
+
+fn new_programme(element: &BytesStart) -> Result<Programme> {
+    Ok(Programme {
+        channel: attribute(element, b"channel")?.unwrap_or_default(),
+        start: attribute(element, b"start")?.as_deref().and_then(parse_time),
+        stop: attribute(element, b"stop")?.as_deref().and_then(parse_time),
+        title: String::new(),
+    })
+}
+
+fn attribute(element: &BytesStart, name: &[u8]) -> Result<Option<String>> {
+    match element.try_get_attribute(name)? {
+        Some(attribute) => Ok(Some(attribute.unescape_value()?.into_owned())),
+        None => Ok(None),
+    }
+}
+
+/// XMLTV time, `YYYYMMDDhhmmss +hhmm`; UTC when the offset is missing
+fn parse_time(value: &str) -> Option<DateTime<FixedOffset>> {
+    let compact: String = value.split_whitespace().collect();
+    DateTime::parse_from_str(&compact, "%Y%m%d%H%M%S%z").ok().or_else(|| {
+        NaiveDateTime::parse_from_str(compact.get(..14)?, "%Y%m%d%H%M%S")
+            .ok()
+            .map(|time| time.and_utc().fixed_offset())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use super::*;
+    use crate::catalog;
+    use crate::m3u;
+
+    const GUIDE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tv generator-info-name="test">
+  <channel id="La1.es">
+    <display-name lang="es">La 1</display-name>
+    <display-name lang="en">TVE 1</display-name>
+  </channel>
+  <channel id="news.uk"><display-name><![CDATA[BBC News]]></display-name></channel>
+  <channel id="empty.id"/>
+  <programme start="20241018200000 +0200" stop="20241018210000 +0200" channel="La1.es">
+    <title lang="es">Telediario</title>
+    <title lang="en">News</title>
+  </programme>
+  <programme start="20241018190000 +0200" stop="20241018200000 +0200" channel="La1.es">
+    <title>Aquí la Tierra</title>
+  </programme>
+  <programme start="20241018180000" channel="news.uk"><title>World News</title></programme>
+</tv>"#;
+
+    const PLAYLIST: &str = "#EXTM3U
+#EXTINF:-1 tvg-id=\"la1.ES\",La 1 HD
+http://10.0.0.1/1
+#EXTINF:-1,La 1
+http://10.0.0.2/1
+#EXTINF:-1,TVE 1
+http://10.0.0.1/2
+#EXTINF:-1,BBC News FHD
+http://10.0.0.1/3
+#EXTINF:-1 tvg-id=\"nope\",Unknown
+http://10.0.0.1/4
+#EXTINF:-1 tvg-name=\"La 1\",Canal Uno
+http://10.0.0.1/5
+";
+
+    fn guide() -> Guide {
+        Guide::parse(GUIDE.as_bytes()).unwrap()
+    }
+
+    fn channels() -> Vec<Channel> {
+        m3u::parse(PLAYLIST).entries.iter()
+            .map(|entry| Channel::from_entry(entry, &entry.uri, None))
+            .collect()
+    }
+
+    fn offset(hours: i32) -> FixedOffset {
+        FixedOffset::east_opt(hours * 3600).unwrap()
+    }
+
+    #[test]
+    fn times_with_and_without_an_offset() {
+        assert_eq!(parse_time("20241018203000 +0200"), offset(2).with_ymd_and_hms(2024, 10, 18, 20, 30, 0).single());
+        assert_eq!(parse_time("20241018203000+0200"), offset(2).with_ymd_and_hms(2024, 10, 18, 20, 30, 0).single());
+        assert_eq!(
+            parse_time("20241018203000 -0530"),
+            FixedOffset::west_opt(5 * 3600 + 30 * 60).unwrap().with_ymd_and_hms(2024, 10, 18, 20, 30, 0).single()
+        );
+        assert_eq!(parse_time("20241018203000"), offset(0).with_ymd_and_hms(2024, 10, 18, 20, 30, 0).single());
+        assert_eq!(parse_time("2024101820"), None);
+        assert_eq!(parse_time("tomorrow"), None);
+    }
+
+    #[test]
+    fn parses_channels_and_programmes() {
+        let guide = guide();
+        assert_eq!(guide.channels.len(), 3);
+        assert_eq!(guide.channels[0].display_names, ["La 1", "TVE 1"]);
+        assert_eq!(guide.channels[1].display_names, ["BBC News"]);
+        assert!(guide.channels[2].display_names.is_empty());
+        assert_eq!(guide.programme_count(), 3);
+
+        // In start order, with the first title of each
+        let titles: Vec<&str> = guide.programmes("La1.es").iter().map(|programme| programme.title.as_str()).collect();
+        assert_eq!(titles, ["Aquí la Tierra", "Telediario"]);
+        assert_eq!(guide.programmes("news.uk")[0].stop, None);
+
+        // 18:30 UTC is 20:30 in the guide's +0200
+        let now = Utc.with_ymd_and_hms(2024, 10, 18, 18, 30, 0).unwrap();
+        let upcoming: Vec<&str> = guide.upcoming("La1.es", now).map(|programme| programme.title.as_str()).collect();
+        assert_eq!(upcoming, ["Telediario"]);
+    }
+
+    #[test]
+    fn matches_channels_by_id_then_display_name() {
+        let guide = guide();
+        let found: Vec<Option<(&str, MatchKind)>> = channels().iter()
+            .map(|channel| guide.find(channel).map(|(guide_channel, kind)| (guide_channel.id.as_str(), kind)))
+            .collect();
+
+        assert_eq!(found, [
+            Some(("La1.es", MatchKind::TvgId)),
+            Some(("La1.es", MatchKind::Name)),
+            Some(("La1.es", MatchKind::Name)),
+            Some(("news.uk", MatchKind::Name)),
+            None,
+            Some(("La1.es", MatchKind::Name)),
+        ]);
+    }
+
+    #[test]
+    fn coverage_counts_logical_channels() {
+        // `La 1` joins the `tvg-id` group of `La 1 HD`, which counts by id
+        let groups = catalog::group(channels());
+        assert_eq!(groups.len(), 5);
+
+        let report = coverage(&guide(), &groups);
+        assert_eq!(report.channels, 5);
+        assert_eq!(report.by_tvg_id, 1);
+        assert_eq!(report.by_name, 3);
+        assert_eq!(report.matched(), 4);
+        assert_eq!(report.percent(), 80.0);
+
+        assert_eq!(coverage(&guide(), &[]).percent(), 0.0);
+    }
+}
@@ -0,0 +1,320 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use anyhow::{Context, Result};
+use chrono::Local;
+
+use crate::catalog;
+use crate::channel::Channel;
+use crate::config::OutputConfig;
+use crate::fingerprint::WebFingerprint;
+use crate::playlist::PlaylistAccess;
+use crate::scanner::Server;
+
+/// Directory where exports are written
+pub const EXPORT_DIR: &str = "exports";
+
+const SERVER_COLUMNS: &[&str] = &[
+    "ip",
+    "port",
+    "service",
+    "detector",
+    "confidence",
+    "confirmed",
+    "confirmation",
+    "product",
+    "version",
+    "build",
+    "edition",
+    "discovery_time",
+    "playlist_path",
+    "playlist_access",
+    "tls_subject",
+    "tls_issuer",
+    "tls_sans",
+    "tls_not_before",
+    "tls_not_after",
+    "tls_sha256",
+    "status_line",
+    "content_type",
+    "content_length",
+    "ttfb_ms",
+    "latency_ms",
+    "response_headers",
+    "http_title",
+    "http_server",
+    "http_powered_by",
+    "body_sha256",
+    "body_mmh3",
+    "favicon_sha256",
+    "favicon_mmh3",
+];
+
+/// Export server records as JSON and CSV.
+///
+/// Returns the path of the JSON file, the CSV file is written next to it.
+pub fn export_servers(servers: &[Server]) -> Result<PathBuf> {
+    fs::create_dir_all(EXPORT_DIR)?;
+
+    let timestamp = Local::now().format("%Y%m%d_%H%M%S");
+    let json_path = PathBuf::from(format!("{}/servers_{}.json", EXPORT_DIR, timestamp));
+    let csv_path = json_path.with_extension("csv");
+
+    let json = serde_json::to_string_pretty(servers)
+        .with_context(|| "Failed to serialize servers")?;
+    fs::write(&json_path, json)
+        .with_context(|| format!("Failed to write {:?}", json_path))?;
+
+    let file = File::create(&csv_path)
+        .with_context(|| format!("Failed to create {:?}", csv_path))?;
+    let mut writer = BufWriter::new(file);
+
+    write_csv_row(&mut writer, SERVER_COLUMNS.iter().map(|column| column.to_string()))?;
+    for server in servers {
+        write_csv_row(&mut writer, server_row(server))?;
+    }
+    writer.flush()?;
+
+    Ok(json_path)
+}
+
+const CHANNEL_COLUMNS: &[&str] = &[
+    "name",
+    "canonical_key",
+    "url",
+    "scheme",
+    "verifiable",
+    "group",
+    "logo",
+    "tvg_id",
+    "server",
+    "first_seen",
+    "last_seen",
+    "checked_at",
+    "working",
+];
+
+/// Export channel records as JSON and CSV, and the channels not known to be
+/// down as playlists laid out like `output`'s.
+///
+/// Returns the path of the JSON file; the CSV file and the `playlists_`
+/// directory are written next to it.
+pub fn export_channels(channels: &[Channel], output: &OutputConfig) -> Result<PathBuf> {
+    fs::create_dir_all(EXPORT_DIR)?;
+
+    let timestamp = Local::now().format("%Y%m%d_%H%M%S");
+    let json_path = PathBuf::from(format!("{}/channels_{}.json", EXPORT_DIR, timestamp));
+    let csv_path = json_path.with_extension("csv");
+
+    let json = serde_json::to_string_pretty(channels)
+        .with_context(|| "Failed to serialize channels")?;
+    fs::write(&json_path, json)
+        .with_context(|| format!("Failed to write {:?}", json_path))?;
+
+    let file = File::create(&csv_path)
+        .with_context(|| format!("Failed to create {:?}", csv_path))?;
+    let mut writer = BufWriter::new(file);
+
+    write_csv_row(&mut writer, CHANNEL_COLUMNS.iter().map(|column| column.to_string()))?;
+    for channel in channels {
+        let verification = channel.verification.as_ref();
+        write_csv_row(&mut writer, vec![
+            channel.name.clone(),
+            channel.canonical_key(),
+            channel.url.clone(),
+            channel.scheme().unwrap_or_default(),
+            channel.is_verifiable().to_string(),
+            channel.group.clone().unwrap_or_default(),
+            channel.logo.clone().unwrap_or_default(),
+            channel.tvg_id.clone().unwrap_or_default(),
+            channel.server.clone().unwrap_or_default(),
+            channel.first_seen.to_rfc3339(),
+            channel.last_seen.to_rfc3339(),
+            verification.map(|verification| verification.checked_at.to_rfc3339()).unwrap_or_default(),
+            verification.map(|verification| verification.working.to_string()).unwrap_or_default(),
+        ])?;
+    }
+    writer.flush()?;
+
+    let playable: Vec<Channel> = channels.iter()
+        .filter(|channel| channel.is_working() != Some(false))
+        .cloned()
+        .collect();
+    let playlists_dir = PathBuf::from(format!("{}/playlists_{}", EXPORT_DIR, timestamp));
+    catalog::write(&playable, &playlists_dir, output)?;
+
+    Ok(json_path)
+}
+
+const EXPOSURE_COLUMNS: &[&str] = &[
+    "ip",
+    "port",
+    "service",
+    "version",
+    "confirmed",
+    "playlist_access",
+    "playlist_path",
+    "http_status",
+    "auth_scheme",
+    "auth_realm",
+];
+
+/// Servers counted by how their playlist is exposed
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExposureSummary {
+    /// Playlist served without credentials
+    pub open: usize,
+    /// Playlist behind a login
+    pub protected: usize,
+    /// No playlist at any candidate path
+    pub unavailable: usize,
+    /// Playlist never requested, e.g. unconfirmed servers
+    pub unchecked: usize,
+}
+
+impl ExposureSummary {
+    pub fn of(servers: &[Server]) -> Self {
+        let mut summary = Self::default();
+        for server in servers {
+            match &server.playlist_access {
+                Some(PlaylistAccess::Open) => summary.open += 1,
+                Some(PlaylistAccess::Protected(_)) => summary.protected += 1,
+                Some(PlaylistAccess::Unavailable) => summary.unavailable += 1,
+                None => summary.unchecked += 1,
+            }
+        }
+        summary
+    }
+}
+
+/// Write the playlist exposure report as CSV, openly published playlists
+/// first, then protected ones.
+///
+/// Lets operators see which instances are locked down and which publish
+/// their channel list to anyone.
+pub fn export_exposure(servers: &[Server]) -> Result<(PathBuf, ExposureSummary)> {
+    fs::create_dir_all(EXPORT_DIR)?;
+
+    let timestamp = Local::now().format("%Y%m%d_%H%M%S");
+    let path = PathBuf::from(format!("{}/exposure_{}.csv", EXPORT_DIR, timestamp));
+
+    let file = File::create(&path)
+        .with_context(|| format!("Failed to create {:?}", path))?;
+    let mut writer = BufWriter::new(file);
+
+    let rank = |server: &&Server| match &server.playlist_access {
+        Some(PlaylistAccess::Open) => 0,
+        Some(PlaylistAccess::Protected(_)) => 1,
+        Some(PlaylistAccess::Unavailable) => 2,
+        None => 3,
+    };
+    let mut sorted: Vec<&Server> = servers.iter().collect();
+    sorted.sort_by_key(rank);
+
+    write_csv_row(&mut writer, EXPOSURE_COLUMNS.iter().map(|column| column.to_string()))?;
+    for server in sorted {
+        write_csv_row(&mut writer, exposure_row(server))?;
+    }
+    writer.flush()?;
+
+    Ok((path, ExposureSummary::of(servers)))
+}
+
+fn exposure_row(server: &Server) -> Vec<String> {
+    let access = server.playlist_access.as_ref();
+    let protection = match access {
+        Some(PlaylistAccess::Protected(protection)) => Some(protection),
+        _ => None,
+    };
+
+    vec![
+        server.ip.to_string(),
+        server.port.to_string(),
+        server.service.clone(),
+        server.version_label(),
+        server.confirmed.to_string(),
+        access.map(|access| access.label().to_string()).unwrap_or_else(|| "unchecked".to_string()),
+        protection.map(|protection| protection.path.clone())
+            .or_else(|| server.playlist_path.clone())
+            .unwrap_or_default(),
+        protection.map(|protection| protection.status.to_string()).unwrap_or_default(),
+        protection.and_then(|protection| protection.scheme.clone()).unwrap_or_default(),
+        protection.and_then(|protection| protection.realm.clone()).unwrap_or_default(),
+    ]
+}
+
+fn server_row(server: &Server) -> Vec<String> {
+    let tls = server.tls.as_ref();
+    let detection = server.detection.as_ref();
+    let web = server.web.as_ref();
+    let response = server.response.as_ref();
+    let web_text = |field: fn(&WebFingerprint) -> &Option<String>| {
+        web.and_then(|web| field(web).clone()).unwrap_or_default()
+    };
+    let web_hash = |field: fn(&WebFingerprint) -> Option<i32>| {
+        web.and_then(field).map(|hash| hash.to_string()).unwrap_or_default()
+    };
+    let format_time = |time: Option<chrono::DateTime<chrono::Utc>>| {
+        time.map(|time| time.to_rfc3339()).unwrap_or_default()
+    };
+
+    vec![
+        server.ip.to_string(),
+        server.port.to_string(),
+        server.service.clone(),
+        detection.map(|detection| detection.detector.clone()).unwrap_or_default(),
+        detection.map(|detection| detection.confidence.to_string()).unwrap_or_default(),
+        server.confirmed.to_string(),
+        detection.and_then(|detection| detection.confirmation.as_ref())
+            .map(|confirmation| confirmation.evidence.clone())
+            .unwrap_or_default(),
+        server.product.clone().unwrap_or_default(),
+        server.version.clone().unwrap_or_default(),
+        server.build.clone().unwrap_or_default(),
+        server.edition.clone().unwrap_or_default(),
+        server.discovery_time.to_rfc3339(),
+        server.playlist_path.clone().unwrap_or_default(),
+        server.playlist_access.as_ref().map(|access| access.label().to_string()).unwrap_or_default(),
+        tls.map(|cert| cert.subject.clone()).unwrap_or_default(),
+        tls.map(|cert| cert.issuer.clone()).unwrap_or_default(),
+        tls.map(|cert| cert.sans.join(";")).unwrap_or_default(),
+        format_time(tls.and_then(|cert| cert.not_before)),
+        format_time(tls.and_then(|cert| cert.not_after)),
+        tls.map(|cert| cert.sha256.clone()).unwrap_or_default(),
+        response.map(|meta| meta.status_line.clone()).unwrap_or_default(),
+        response.and_then(|meta| meta.content_type.clone()).unwrap_or_default(),
+        response.and_then(|meta| meta.content_length).map(|length| length.to_string()).unwrap_or_default(),
+        response.map(|meta| meta.ttfb_ms.to_string()).unwrap_or_default(),
+        response.map(|meta| meta.latency_ms.to_string()).unwrap_or_default(),
+        response.map(|meta| {
+            meta.headers.iter()
+                .map(|(name, value)| format!("{}: {}", name, value))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }).unwrap_or_default(),
+        web_text(|web| &web.title),
+        web_text(|web| &web.server),
+        web_text(|web| &web.powered_by),
+        web_text(|web| &web.body_sha256),
+        web_hash(|web| web.body_mmh3),
+        web_text(|web| &web.favicon_sha256),
+        web_hash(|web| web.favicon_mmh3),
+    ]
+}
+
+/// Write one CSV record, quoting fields as needed
+pub fn write_csv_row<W: Write>(writer: &mut W, fields: impl IntoIterator<Item = String>) -> Result<()> {
+    let line = fields.into_iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+
+    writeln!(writer, "{}", line)?;
+    Ok(())
+}
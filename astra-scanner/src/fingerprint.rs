@@ -0,0 +1,145 @@
+use std::time::Duration;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::header::{HeaderMap, SERVER};
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::http;
+use crate::utils::{mmh3_32, sha256_hex};
+
+/// Most bytes of a landing page that are hashed
+const MAX_PAGE_BYTES: usize = 1024 * 1024;
+
+/// Most bytes of a favicon that are hashed
+const MAX_FAVICON_BYTES: usize = 256 * 1024;
+
+lazy_static! {
+    static ref TITLE: Regex = Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap();
+    static ref ICON_LINK: Regex = Regex::new(r#"(?is)<link\b[^>]*\brel\s*=\s*["']?(?:shortcut\s+)?icon["']?[^>]*>"#).unwrap();
+    static ref HREF: Regex = Regex::new(r#"(?is)\bhref\s*=\s*["']?([^"'\s>]+)"#).unwrap();
+}
+
+/// Pivot attributes of a server's web interface.
+///
+/// The hashes follow internet-scan dataset conventions so results can be
+/// cross-referenced: `body_mmh3` is MurmurHash3 of the raw page and
+/// `favicon_mmh3` is MurmurHash3 of the favicon's base64 encoding with line
+/// breaks every 76 characters. The certificate hash lives on `Server::tls`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebFingerprint {
+    pub title: Option<String>,
+    /// `Server` header of the landing page
+    pub server: Option<String>,
+    /// `X-Powered-By` header of the landing page
+    pub powered_by: Option<String>,
+    pub body_sha256: Option<String>,
+    pub body_mmh3: Option<i32>,
+    pub favicon_sha256: Option<String>,
+    pub favicon_mmh3: Option<i32>,
+}
+
+impl WebFingerprint {
+    /// Fetch the landing page and favicon of `base_url`.
+    ///
+    /// Whatever could not be fetched is left empty.
+    pub async fn collect(client: &Client, base_url: &str, timeout: Duration) -> Self {
+        let mut fingerprint = Self::default();
+
+        let page_url = format!("{}/", base_url);
+        let mut favicon_url = format!("{}/favicon.ico", base_url);
+
+        if let Some((headers, body)) = fetch(client, &page_url, MAX_PAGE_BYTES, timeout).await {
+            fingerprint.server = header_value(&headers, SERVER.as_str());
+            fingerprint.powered_by = header_value(&headers, "x-powered-by");
+
+            if !body.is_empty() {
+                let html = String::from_utf8_lossy(&body);
+                fingerprint.title = html_title(&html);
+                if let Some(href) = icon_href(&html) {
+                    if let Some(url) = Url::parse(&page_url).ok().and_then(|base| base.join(&href).ok()) {
+                        favicon_url = url.to_string();
+                    }
+                }
+
+                fingerprint.body_sha256 = Some(sha256_hex(&body));
+                fingerprint.body_mmh3 = Some(mmh3_32(&body));
+            }
+        }
+
+        if let Some((_, icon)) = fetch(client, &favicon_url, MAX_FAVICON_BYTES, timeout).await {
+            if !icon.is_empty() {
+                fingerprint.favicon_sha256 = Some(sha256_hex(&icon));
+                fingerprint.favicon_mmh3 = Some(favicon_hash(&icon));
+            }
+        }
+
+        fingerprint
+    }
+
+    /// Whether any field contains `query`, ignoring case
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        let text_fields = [&self.title, &self.server, &self.powered_by, &self.body_sha256, &self.favicon_sha256];
+        let hash_fields = [self.body_mmh3, self.favicon_mmh3];
+
+        text_fields.into_iter().flatten().any(|value| value.to_lowercase().contains(&query))
+            || hash_fields.into_iter().flatten().any(|hash| hash.to_string() == query)
+    }
+}
+
+/// GET `url` and return its headers and body, if it answered with success
+async fn fetch(client: &Client, url: &str, max_bytes: usize, timeout: Duration) -> Option<(HeaderMap, Vec<u8>)> {
+    let response = client.get(url).timeout(timeout).send().await.ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+
+    let headers = response.headers().clone();
+    let sample = http::read_bounded(response, max_bytes, timeout).await.ok()?;
+    Some((headers, sample.bytes))
+}
+
+fn header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers.get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Text of the first `<title>`, with whitespace collapsed
+fn html_title(html: &str) -> Option<String> {
+    let title = TITLE.captures(html)?.get(1)?.as_str();
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!title.is_empty()).then_some(title)
+}
+
+/// `href` of the first `<link rel="icon">`
+fn icon_href(html: &str) -> Option<String> {
+    let link = ICON_LINK.find(html)?.as_str();
+    Some(HREF.captures(link)?.get(1)?.as_str().to_string())
+}
+
+/// MurmurHash3 of the base64 encoding, wrapped like Python's `base64.encodebytes`
+fn favicon_hash(icon: &[u8]) -> i32 {
+    let encoded = STANDARD.encode(icon);
+    let mut wrapped = String::with_capacity(encoded.len() + encoded.len() / 76 + 1);
+    for line in encoded.as_bytes().chunks(76) {
+        wrapped.push_str(std::str::from_utf8(line).unwrap_or_default());
+        wrapped.push('\n');
+    }
+    mmh3_32(wrapped.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn favicon_hash_wraps_like_encodebytes() {
+        // mmh3.hash(base64.encodebytes(bytes(range(100)))), which wraps after 76 characters
+        let icon: Vec<u8> = (0..100).collect();
+        assert_eq!(favicon_hash(&icon), -1165240594);
+    }
+}
@@ -1,16 +1,28 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration;
-use chrono::Local;
 use std::sync::Mutex;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::task::JoinSet;
 
 use iced::{
     Application, Command, Element, Length, Subscription, Theme,
     widget::{column, row, button, text, horizontal_space},
 };
 
-use crate::config::{Config, SimpleScannerConfig};
-use crate::scanner::{SimpleScanner, Server};
+use crate::channel::{self, Channel};
+use crate::catalog::CatalogWriter;
+use crate::config::{Config, ScannerConfig, SimpleScannerConfig, OPT_OUT_URL};
+use crate::detect::DetectorRegistry;
+use crate::epg::{self, Guide};
+use crate::export;
+use crate::fingerprint::WebFingerprint;
+use crate::http;
+use crate::playlist;
+use crate::scanner::{fetch_server_playlist, report_playlist_warnings, SimpleScanner, Server};
+use crate::snapshot;
+use crate::verify::{self, FileVerification};
+use crate::store::{self, FoundServers, RawResponseLog};
 
 use crate::gui::{
     message::Message,
@@ -33,6 +45,18 @@ pub enum View {
     AstraServer,
 }
 
+// Versiones guardadas de la playlist de un servidor y cambios entre dos de
+// ellas, leídas del disco al abrir el panel o al elegir otra versión
+#[derive(Debug, Clone)]
+pub struct PlaylistHistory {
+    pub server: std::net::SocketAddr,
+    // Versión comparada con la última; la penúltima si no se eligió ninguna
+    pub compared: Option<String>,
+    // La más antigua primero
+    pub snapshots: Vec<snapshot::Snapshot>,
+    pub changes: Result<(snapshot::PlaylistDiff, snapshot::Snapshot, snapshot::Snapshot), String>,
+}
+
 // Enum para controlar qué tipo de resultados mostrar
 #[derive(Debug, Clone, Copy)]
 pub enum ResultsView {
@@ -58,6 +82,14 @@ pub struct AstraApp {
     channels_found: usize,
     results_view: ResultsView, // Qué tipo de resultados mostrar: servidores o canales
     channels_search: String,   // Texto para buscar canales por nombre
+    channel_groups_expanded: bool, // Si los grupos de fuentes de un canal se muestran abiertos
+    toggled_channel_groups: HashSet<String>, // Grupos abiertos o cerrados a mano, al contrario que el resto
+    servers_search: String,    // Filtro de servidores (título, hashes, encabezados...)
+    selected_server: Option<std::net::SocketAddr>, // Servidor con el panel de detalles abierto
+    compared_snapshot: Option<String>, // Versión de su playlist que se compara con la última
+    playlist_history: Option<Arc<PlaylistHistory>>, // Versiones y cambios del servidor seleccionado, una vez leídos
+    selected_channel: Option<String>, // URL del canal con el panel de detalles abierto
+    guide: Option<Arc<Guide>>, // Guía EPG (XMLTV) configurada en epg.file
 }
 
 impl AstraApp {
@@ -80,6 +112,14 @@ impl AstraApp {
             channels_found: 0,
             results_view: ResultsView::Servers,
             channels_search: String::new(),
+            channel_groups_expanded: false,
+            toggled_channel_groups: HashSet::new(),
+            servers_search: String::new(),
+            selected_server: None,
+            compared_snapshot: None,
+            playlist_history: None,
+            selected_channel: None,
+            guide: None,
         }
     }
     
@@ -142,6 +182,58 @@ impl AstraApp {
         self.channels_search = search;
     }
     
+    // Si todos los grupos de canales están abiertos por defecto
+    pub fn channel_groups_expanded(&self) -> bool {
+        self.channel_groups_expanded
+    }
+    
+    // Si el grupo con esta clave canónica muestra sus fuentes
+    pub fn is_channel_group_expanded(&self, key: &str) -> bool {
+        self.channel_groups_expanded != self.toggled_channel_groups.contains(key)
+    }
+    
+    // Getter para el filtro de servidores
+    pub fn get_servers_search(&self) -> &str {
+        &self.servers_search
+    }
+    
+    // Setter para el filtro de servidores
+    pub fn set_servers_search(&mut self, search: String) {
+        self.servers_search = search;
+    }
+    
+    // Servidor seleccionado para el panel de detalles
+    pub fn get_selected_server(&self) -> Option<&Server> {
+        let selected = self.selected_server?;
+        self.servers.iter().find(|server| server.addr() == selected)
+    }
+    
+    // URL del canal seleccionado para el panel de detalles
+    pub fn get_selected_channel(&self) -> Option<&str> {
+        self.selected_channel.as_deref()
+    }
+    
+    // Guía EPG cargada, si la hay
+    pub fn get_guide(&self) -> Option<&Guide> {
+        self.guide.as_deref()
+    }
+    
+    // Historial de la playlist del servidor seleccionado; `None` mientras se lee
+    pub fn get_playlist_history(&self) -> Option<&PlaylistHistory> {
+        self.playlist_history.as_deref()
+    }
+    
+    // Leer el historial de la playlist del servidor seleccionado fuera de `view()`
+    fn load_playlist_history(&self) -> Command<Message> {
+        match self.selected_server {
+            Some(server) => Command::perform(
+                load_playlist_history(server, self.compared_snapshot.clone()),
+                Message::PlaylistHistoryLoaded,
+            ),
+            None => Command::none(),
+        }
+    }
+    
     // Create navigation tabs
     pub fn create_tabs(&self) -> Element<'_, Message> {
         let dashboard_tab = button(
@@ -255,7 +347,15 @@ impl Application for AstraApp {
     type Flags = ();
 
     fn new(_flags: Self::Flags) -> (Self, Command<Message>) {
-        (Self::new(), Command::none())
+        let app = Self::new();
+        
+        // Cargar la guía EPG configurada, si la hay
+        let command = match app.config.epg.file.clone() {
+            Some(path) => Command::perform(load_guide(path), Message::GuideLoaded),
+            None => Command::none(),
+        };
+        
+        (app, command)
     }
 
     fn title(&self) -> String {
@@ -278,19 +378,8 @@ impl Application for AstraApp {
                     // and use subscription to update progress
                     
                     // For demo purposes, simulate finding some servers
-                    self.servers.push(Server {
-                        ip: "192.168.1.1".parse().unwrap(),
-                        port: 80,
-                        service: "http".to_string(),
-                        discovery_time: Local::now(),
-                    });
-                    
-                    self.servers.push(Server {
-                        ip: "192.168.1.5".parse().unwrap(),
-                        port: 22,
-                        service: "ssh".to_string(),
-                        discovery_time: Local::now(),
-                    });
+                    self.servers.push(Server::new("192.168.1.1".parse().unwrap(), 80, "http"));
+                    self.servers.push(Server::new("192.168.1.5".parse().unwrap(), 22, "ssh"));
                 }
                 Command::none()
             }
@@ -377,6 +466,10 @@ impl Application for AstraApp {
                 self.config.scanner.channel_timeout = value;
                 Command::none()
             }
+            Message::ConfidenceThresholdChanged(value) => {
+                self.config.scanner.confidence_threshold = value;
+                Command::none()
+            }
             Message::PoolConnectionsChanged(value) => {
                 self.config.scanner.pool_connections = value;
                 Command::none()
@@ -455,12 +548,14 @@ impl Application for AstraApp {
                 Command::none()
             }
             Message::ViewServerDetails(ip, port) => {
-                // In a real app, you would show details for this server
-                println!("View details for {}:{}", ip, port);
+                // Abrir el panel de detalles (respuesta HTTP completa)
+                self.selected_server = Some(std::net::SocketAddr::new(ip, port));
+                self.compared_snapshot = None;
+                self.playlist_history = None;
                 
                 // Mostrar el país del servidor
-                return Command::perform(
-                    get_ip_country(&ip),
+                let country = Command::perform(
+                    get_ip_country(self.config.probe.clone(), ip),
                     move |result| {
                         let country_info = match result {
                             Ok(country) => country,
@@ -475,24 +570,88 @@ impl Application for AstraApp {
                         Message::UpdateStatus(format!("Servidor {}:{} - País: {}", ip, port, country_info))
                     }
                 );
+                return Command::batch([country, self.load_playlist_history()]);
+            }
+            Message::CloseServerDetails => {
+                self.selected_server = None;
+                self.compared_snapshot = None;
+                self.playlist_history = None;
+                Command::none()
+            }
+            Message::ViewChannelDetails(url) => {
+                self.selected_channel = Some(url);
+                Command::none()
+            }
+            Message::CloseChannelDetails => {
+                self.selected_channel = None;
+                Command::none()
+            }
+            Message::GuideLoaded(result) => {
+                match result {
+                    Ok(guide) => {
+                        self.status = format!("Guía EPG cargada: {} canales, {} programas", guide.channels.len(), guide.programme_count());
+                        self.guide = Some(guide);
+                    }
+                    Err(e) => {
+                        self.status = format!("Error cargando la guía EPG: {}", e);
+                    }
+                }
+                Command::none()
+            }
+            Message::CompareSnapshot(id) => {
+                self.compared_snapshot = Some(id);
+                self.load_playlist_history()
+            }
+            Message::PlaylistHistoryLoaded(history) => {
+                // Descartar lecturas de otro servidor o de otra versión elegida antes
+                if self.selected_server == Some(history.server) && self.compared_snapshot == history.compared {
+                    self.playlist_history = Some(history);
+                }
+                Command::none()
             }
             Message::DownloadServerPlaylist(ip, port) => {
                 // Descargar la playlist del servidor
                 self.status = format!("Descargando playlist de {}:{}...", ip, port);
                 
+                // Usar https si el servidor respondió por TLS, y las rutas de playlist de su producto
+                let server = self.servers.iter()
+                    .find(|server| server.ip == ip && server.port == port);
+                let base_url = server
+                    .map(|server| server.base_url())
+                    .unwrap_or_else(|| format!("http://{}", std::net::SocketAddr::new(ip, port)));
+                // Una ruta que ya pidió credenciales al confirmar el servidor no se vuelve a pedir
+                let protection = server.and_then(|server| server.confirmed_protection()).cloned();
+                let playlist_paths = match server {
+                    Some(server) => {
+                        let paths = playlist::candidate_paths(server, &self.config.scanner);
+                        if paths.is_empty() {
+                            self.status = format!("{} ({}:{}) no publica playlists", server.service, ip, port);
+                            return Command::none();
+                        }
+                        paths
+                    },
+                    None => vec!["/playlist.m3u".to_string()],
+                };
+                
                 return Command::perform(
-                    download_server_playlist(&ip, port),
-                    move |result| {
-                        match result {
-                            Ok(_) => {
-                                Message::UpdateStatus(format!("Playlist de {}:{} descargada correctamente", ip, port))
-                            },
-                            Err(e) => {
-                                Message::UpdateStatus(format!("Error al descargar la playlist: {}", e))
-                            }
+                    download_server_playlist(self.config.clone(), base_url, playlist_paths, protection, ip, port),
+                    move |result| Message::ServerPlaylistDownloaded(ip, port, result)
+                );
+            }
+            Message::ServerPlaylistDownloaded(ip, port, result) => {
+                match result {
+                    Ok(path) => {
+                        self.status = format!("Playlist de {}:{} descargada correctamente ({})", ip, port, path);
+                        // La descarga guardó una versión nueva en el historial
+                        if self.selected_server == Some(std::net::SocketAddr::new(ip, port)) {
+                            return self.load_playlist_history();
                         }
+                    },
+                    Err(e) => {
+                        self.status = format!("Error al descargar la playlist: {}", e);
                     }
-                );
+                }
+                Command::none()
             }
             Message::ShowServerCountry(ip) => {
                 // Aquí se manejaría la visualización del país en una interfaz más completa
@@ -552,8 +711,37 @@ impl Application for AstraApp {
                 Command::none()
             },
             Message::ExportResults => {
-                // In a real app, you would export the results
-                println!("Exporting {} server results", self.servers.len());
+                let channels = store::load_channel_records();
+                match export::export_servers(&self.servers)
+                    .and_then(|path| Ok((path, export::export_exposure(&self.servers)?)))
+                    .and_then(|exported| Ok((exported, export::export_channels(&channels, &self.config.output)?))) {
+                    Ok(((path, (_, exposure)), _)) => {
+                        self.status = format!("{} servidores y {} canales exportados a {} (playlists: {} abiertas, {} protegidas)",
+                            self.servers.len(), channels.len(), path.parent().unwrap_or(&path).display(), exposure.open, exposure.protected);
+                    },
+                    Err(e) => {
+                        self.status = format!("Error exportando resultados: {}", e);
+                    }
+                }
+                Command::none()
+            }
+            Message::VerifyPlaylistFile => {
+                self.status = "Elige una playlist para verificar".to_string();
+                Command::perform(pick_and_verify_playlist(self.config.clone()), Message::PlaylistFileVerified)
+            }
+            Message::PlaylistFileVerified(result) => {
+                match result {
+                    Ok(Some(verification)) => {
+                        self.status = format!("Playlist verificada: {} entradas, {} funcionan, {} fallan, {} sin verificar. Informe en {}",
+                            verification.entries, verification.working, verification.failed, verification.unverified, verification.report.display());
+                    }
+                    Ok(None) => {
+                        self.status = "Verificación cancelada".to_string();
+                    }
+                    Err(e) => {
+                        self.status = format!("Error verificando la playlist: {}", e);
+                    }
+                }
                 Command::none()
             }
             Message::CreateIPFile => {
@@ -588,6 +776,21 @@ impl Application for AstraApp {
                 self.set_channels_search(search);
                 Command::none()
             },
+            Message::ServersSearchChanged(search) => {
+                self.set_servers_search(search);
+                Command::none()
+            },
+            Message::ToggleChannelGroup(key) => {
+                if !self.toggled_channel_groups.remove(&key) {
+                    self.toggled_channel_groups.insert(key);
+                }
+                Command::none()
+            },
+            Message::SetChannelGroupsExpanded(expanded) => {
+                self.channel_groups_expanded = expanded;
+                self.toggled_channel_groups.clear();
+                Command::none()
+            },
             Message::PlayChannel(url) => {
                 // Intentar reproducir con un reproductor externo
                 #[cfg(target_os = "windows")]
@@ -737,8 +940,9 @@ impl Application for AstraApp {
 impl AstraApp {
     async fn perform_astra_scan(scanner: Arc<Mutex<SimpleScanner>>, config: Config) -> Result<usize, String> {
         use std::path::Path;
-        use std::fs::{self, File, OpenOptions};
-        use std::io::{BufRead, BufReader, Write};
+        use std::fs::{self, File};
+        use std::io::{BufRead, BufReader};
+        use std::net::{IpAddr, SocketAddr};
         use tokio::time::sleep;
         use std::time::Duration;
         use std::sync::atomic::{AtomicUsize, Ordering};
@@ -783,35 +987,47 @@ impl AstraApp {
             return Err("No hay puertos en el archivo pool/ports.txt".to_string());
         }
         
-        // Preparar para guardar los resultados
-        let found_servers_path = Path::new("found_servers.txt");
-        let found_servers_file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(found_servers_path)
-            .map_err(|e| format!("Error abriendo found_servers.txt: {}", e))?;
-        let writer = std::io::BufWriter::new(found_servers_file);
-        let found_servers_writer = Arc::new(std::sync::Mutex::new(writer));
+        // Servidores ya conocidos (de este u otros escaneos) se deduplican aquí
+        let found_servers = Arc::new(FoundServers::load());
         
         // 3. Comenzar el escaneo
         let total_combinations = ips.len() * ports.len();
         let scanned = Arc::new(AtomicUsize::new(0));
         let found_servers_count = Arc::new(AtomicUsize::new(0));
         
+        if config.probe.contact().is_none() {
+            eprintln!("Aviso: probe.from no está configurado; los destinos solo pueden contactar a través de {}", OPT_OUT_URL);
+        }
+        
         // Crear cliente HTTP para verificación real usando la configuración
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs_f64(config.scanner.connection_timeout))
-            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
-            .build()
+        // (ligado a la dirección de origen configurada, si la hay)
+        let source_address = http::source_address(&config)
+            .map_err(|e| format!("Error en la dirección de origen: {}", e))?;
+        let client = http::client_builder(&config, Duration::from_secs_f64(config.scanner.connection_timeout))
+            .and_then(|builder| Ok(builder.build()?))
             .map_err(|e| format!("Error creando cliente HTTP: {}", e))?;
         
         // Configurar el número máximo de workers
         let max_workers = config.scanner.workers;
         let batch_size = config.scanner.batch_size.min(total_combinations);
         
+        // Detectores, perfil de sondeo y límites de lectura compartidos por todos los workers
+        let raw_log = RawResponseLog::open()
+            .map_err(|e| format!("No se pudo abrir el registro de respuestas: {}", e))?;
+        let detectors = Arc::new(DetectorRegistry::load()
+            .map_err(|e| format!("Error en las reglas de huellas: {:#}", e))?
+            .with_raw_log(Arc::new(raw_log)));
+        let probe = Arc::new(config.probe.clone());
+        let scanner_config = Arc::new(config.scanner.clone());
+        let catalog = CatalogWriter::spawn(config.output.clone())
+            .map_err(|e| format!("No se pudo abrir la playlist de canales: {:#}", e))?;
+        
         // Crear un pool de tareas con límite de concurrencia
         let semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(max_workers));
         
+        // Tareas de playlist, para esperarlas antes de cerrar el escaneo
+        let playlist_tasks = Arc::new(Mutex::new(JoinSet::new()));
+        
         // Procesar en batches
         for (_batch_index, ips_batch) in ips.chunks(batch_size).enumerate() {
             for ip in ips_batch {
@@ -824,60 +1040,83 @@ impl AstraApp {
                         scanner_lock.set_progress(progress);
                     }
                     
-                    // Verificar si es un servidor Astra (como en la versión CLI)
-                    let _server = format!("{}:{}", ip, port);
-                    let url = format!("http://{}:{}", ip, port);
+                    // Ejecutar los detectores (como en la versión CLI)
+                    let ip_addr: IpAddr = match ip.trim().parse() {
+                        Ok(ip_addr) => ip_addr,
+                        Err(_) => continue,
+                    };
+                    let server_addr = SocketAddr::new(ip_addr, port);
+                    let try_tls = config.scanner.tls_ports.contains(&port);
                     
                     // Obtener permiso del semáforo (limitar concurrencia)
                     let permit = semaphore.clone().acquire_owned().await.unwrap();
                     
                     // Clonar lo necesario para el worker
                     let client_clone = client.clone();
-                    let ip_clone = ip.clone();
+                    let detectors_clone = detectors.clone();
+                    let probe_clone = probe.clone();
+                    let scanner_config_clone = scanner_config.clone();
+                    let catalog_clone = catalog.clone();
                     let scanner_clone = scanner.clone();
-                    let found_servers_writer_clone = found_servers_writer.clone();
+                    let found_servers_clone = found_servers.clone();
                     let found_servers_count_clone = found_servers_count.clone();
+                    let playlist_tasks_clone = playlist_tasks.clone();
                     
                     // Lanzar tarea
                     tokio::spawn(async move {
                         // Al salir del scope, el permiso se libera automáticamente
                         let _permit = permit;
                         
-                        match check_server(&client_clone, &url, Duration::from_secs_f64(config.scanner.timeout)).await {
-                            Ok(true) => {
-                                // Servidor Astra encontrado - incrementar contador atómico
-                                found_servers_count_clone.fetch_add(1, Ordering::SeqCst);
+                        match detectors_clone.run(&client_clone, &probe_clone, server_addr, try_tls, Duration::from_secs_f64(config.scanner.timeout)).await {
+                            Some(hit) => {
+                                let base_url = hit.base_url.clone();
+                                let mut server_obj = Server::from_hit(server_addr, hit, config.scanner.confidence_threshold);
+                                // Solo los servidores confirmados entran en el archivo de encontrados
+                                // (si no se conocían ya); los demás se vuelven a sondear en otros escaneos
+                                let is_new = !server_obj.confirmed || match found_servers_clone.record(server_addr) {
+                                    Ok(is_new) => is_new,
+                                    Err(e) => {
+                                        eprintln!("Error guardando servidor {}: {}", server_addr, e);
+                                        false
+                                    }
+                                };
                                 
-                                // Guardar en archivo
-                                let server_str = format!("{}:{}\n", ip_clone, port);
-                                if let Ok(mut writer) = found_servers_writer_clone.lock() {
-                                    let _ = writer.write_all(server_str.as_bytes());
-                                    let _ = writer.flush();
+                                // Guardar el registro completo (versión, build, edición,
+                                // título, encabezados y hashes de la interfaz web)
+                                if is_new {
+                                    server_obj.web = Some(WebFingerprint::collect(&client_clone, &base_url, Duration::from_secs_f64(config.scanner.timeout)).await);
+                                    if let Err(e) = store::append_server_record(&server_obj) {
+                                        eprintln!("Error guardando registro de {}: {}", server_addr, e);
+                                    }
                                 }
                                 
                                 // Registrar en el scanner
-                                let server_obj = crate::scanner::Server {
-                                    ip: ip_clone.parse().unwrap_or("0.0.0.0".parse().unwrap()),
-                                    port,
-                                    service: "http".to_string(),
-                                    discovery_time: chrono::Local::now(),
-                                };
-                                
                                 if let Ok(mut scanner_lock) = scanner_clone.lock() {
-                                    scanner_lock.add_server(server_obj);
+                                    scanner_lock.add_server(server_obj.clone());
                                 }
                                 
+                                // Sin confirmar (posible proxy o honeypot): no cuenta como encontrado
+                                // y no se descarga la playlist
+                                if !is_new || !server_obj.confirmed {
+                                    return;
+                                }
+                                
+                                // Servidor nuevo - incrementar contador atómico
+                                found_servers_count_clone.fetch_add(1, Ordering::SeqCst);
+                                
                                 // Intentar obtener la playlist (opcional)
-                                tokio::spawn(process_playlist(
-                                    client_clone.clone(), 
-                                    ip_clone.clone(), 
-                                    port,
-                                    Duration::from_secs(config.scanner.playlist_timeout as u64),
-                                    Duration::from_secs(config.scanner.channel_timeout as u64)
-                                ));
+                                if let Ok(mut playlist_tasks) = playlist_tasks_clone.lock() {
+                                    playlist_tasks.spawn(process_playlist(
+                                        client_clone.clone(), 
+                                        server_obj,
+                                        scanner_clone,
+                                        scanner_config_clone,
+                                        catalog_clone
+                                    ));
+                                }
                             },
-                            _ => {
-                                // No es un servidor Astra o error, continuar
+                            None => {
+                                // Ningún detector coincide o error, continuar
                             }
                         }
                     });
@@ -891,15 +1130,26 @@ impl AstraApp {
             sleep(Duration::from_millis(100)).await;
         }
         
-        // Finalizar escritura
-        found_servers_writer.lock().unwrap().flush()
-            .map_err(|e| format!("Error finalizando escritura: {}", e))?;
+        // Esperar a los workers (cada uno guarda su permiso hasta terminar),
+        // después a sus playlists, y vaciar la playlist de canales
+        let _all_workers = semaphore.acquire_many(max_workers as u32).await
+            .map_err(|e| format!("Error esperando a los workers: {}", e))?;
+        let mut playlist_tasks = playlist_tasks.lock()
+            .map(|mut tasks| std::mem::take(&mut *tasks))
+            .unwrap_or_default();
+        while let Some(result) = playlist_tasks.join_next().await {
+            if let Err(e) = result {
+                eprintln!("Error en una tarea de playlist: {}", e);
+            }
+        }
+        catalog.flush().await
+            .map_err(|e| format!("Error guardando la playlist de canales: {:#}", e))?;
         
         // Obtener el número final de servidores encontrados
         let total_found = found_servers_count.load(Ordering::SeqCst);
         
         // Guardar resumen del escaneo
-        save_scan_summary(scanned.load(Ordering::SeqCst), total_found).await
+        save_scan_summary(scanned.load(Ordering::SeqCst), total_found, &found_servers.found_this_run(), source_address).await
             .map_err(|e| format!("Error guardando resumen: {}", e))?;
         
         // Completado exitosamente
@@ -907,77 +1157,34 @@ impl AstraApp {
     }
 }
 
-/// Verifica si una URL corresponde a un servidor Astra
-async fn check_server(client: &reqwest::Client, url: &str, timeout: Duration) -> Result<bool, reqwest::Error> {
-    // Realizar petición HEAD
-    let response = client.head(url).timeout(timeout).send().await?;
+/// Procesa la playlist de un servidor, probando cada ruta candidata
+async fn process_playlist(client: reqwest::Client, mut server: Server, scanner: Arc<Mutex<SimpleScanner>>, scanner_config: Arc<ScannerConfig>, catalog: CatalogWriter) {
+    let result = fetch_server_playlist(&client, &mut server, &scanner_config).await;
     
-    // Verificar encabezado del servidor
-    if let Some(server_header) = response.headers().get(reqwest::header::SERVER) {
-        if let Ok(server_value) = server_header.to_str() {
-            if server_value.contains("Astra") {
-                return Ok(true);
-            }
-        }
+    // Guardar el acceso (abierta o protegida) y la ruta también en la lista de resultados
+    if let Ok(mut scanner_lock) = scanner.lock() {
+        scanner_lock.update_server(server.clone());
     }
     
-    Ok(false)
-}
-
-/// Procesa la playlist de un servidor Astra
-async fn process_playlist(client: reqwest::Client, ip: String, port: u16, playlist_timeout: Duration, channel_timeout: Duration) {
-    // Intentar obtener la playlist
-    let url = format!("http://{}:{}/playlist.m3u", ip, port);
+    // Protegida: se registra y no se vuelve a intentar con credenciales
+    let playlist = match result {
+        Ok(playlist) => playlist,
+        Err(e) => {
+            if let Some(protection) = e.downcast_ref::<playlist::Protection>() {
+                println!("Playlist protegida en {}: {}", server.base_url(), protection);
+            }
+            return;
+        }
+    };
     
-    let playlist_result = match client.get(&url)
-        .timeout(playlist_timeout)
-        .send()
-        .await {
-            Ok(response) => {
-                if response.status().is_success() {
-                    match response.text().await {
-                        Ok(content) => {
-                            if content.contains("#EXTM3U") {
-                                Ok(content)
-                            } else {
-                                Err("No es una playlist válida".to_string())
-                            }
-                        },
-                        Err(_) => Err("Error leyendo respuesta".to_string())
-                    }
-                } else {
-                    Err("Respuesta no exitosa".to_string())
-                }
-            },
-            Err(_) => Err("Error de conexión".to_string())
-        };
+    report_playlist_warnings(&server.base_url(), &playlist);
     
-    if let Ok(content) = playlist_result {
-        // Procesar los canales de la playlist con el timeout adecuado
-        process_channels_with_timeout(&content, &format!("{}:{}", ip, port), channel_timeout).await;
-    }
+    // Procesar los canales de la playlist con el timeout adecuado
+    process_channels_with_timeout(&client, playlist.channels(&server.base_url()), &server.base_url(), &scanner_config, &catalog).await;
 }
 
 /// Procesa los canales de una playlist con timeout específico
-async fn process_channels_with_timeout(content: &str, server: &str, timeout: Duration) {
-    // Usamos el timeout para cualquier operación que lo necesite
-    // Este método reemplaza al anterior process_channels pero con soporte para timeout
-    
-    // Extraer canales de la playlist
-    let mut channels = Vec::new();
-    let mut current_title = String::new();
-    
-    for line in content.lines() {
-        if line.starts_with("#EXTINF:") {
-            current_title = line.to_string();
-        } else if line.starts_with("http://") || line.starts_with("https://") {
-            if !current_title.is_empty() {
-                channels.push((current_title.clone(), line.to_string()));
-                current_title = String::new();
-            }
-        }
-    }
-    
+async fn process_channels_with_timeout(client: &reqwest::Client, channels: Vec<Channel>, server: &str, scanner_config: &ScannerConfig, catalog: &CatalogWriter) {
     if channels.is_empty() {
         return;
     }
@@ -987,145 +1194,50 @@ async fn process_channels_with_timeout(content: &str, server: &str, timeout: Dur
         return;
     }
     
-    // Crear cliente HTTP para verificar canales
-    let client = reqwest::Client::builder()
-        .timeout(timeout)
-        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
-        .build()
-        .unwrap_or_else(|_| reqwest::Client::new());
-    
-    // Verificar canales en batches pequeños
-    let batch_size = 10;
-    let mut working_channels = Vec::new();
+    // Los canales que no son HTTP (udp, rtp, rtmp...) se registran sin verificar
+    let verifiable_count = channels.iter().filter(|channel| channel.is_verifiable()).count();
+    println!("Verificando {} canales de {} ({} sin verificar por su protocolo)", verifiable_count, server, channels.len() - verifiable_count);
     
-    println!("Verificando {} canales de {}", channels.len(), server);
+    // Verificar los canales con la concurrencia configurada
+    let channels_count = channels.len();
+    let checked_channels = channel::verify_channels(client, channels, scanner_config).await;
     
-    // Procesar canales en batches para evitar sobrecargar la memoria/red
-    for (_batch_index, batch) in channels.chunks(batch_size).enumerate() {
-        // Crear tareas para verificar cada canal en paralelo
-        let mut handles = Vec::new();
-        
-        for (title, url) in batch {
-            let client_clone = client.clone();
-            let url_clone = url.clone();
-            let title_clone = title.clone();
-            
-            let handle = tokio::spawn(async move {
-                let working = check_channel_working(&client_clone, &url_clone, timeout).await;
-                if working {
-                    println!("✓ Canal funcionando: {}", extract_channel_title(&title_clone));
-                    Some((title_clone, url_clone))
-                } else {
-                    println!("✗ Canal no funciona: {}", extract_channel_title(&title_clone));
-                    None
-                }
-            });
-            
-            handles.push(handle);
-        }
-        
-        // Esperar a que todas las tareas del batch se completen
-        for handle in handles {
-            if let Ok(Some((title, url))) = handle.await {
-                working_channels.push((title, url));
-            }
-        }
-        
-        // Pequeña pausa entre batches para no sobrecargar
-        tokio::time::sleep(Duration::from_millis(100)).await;
+    // Registrar todos los canales con el resultado de su verificación
+    if let Err(e) = store::append_channel_records(&checked_channels) {
+        eprintln!("Error guardando los registros de canales: {}", e);
     }
     
-    println!("De {} canales en {}, {} están funcionando", channels.len(), server, working_channels.len());
-    
-    // Guardar canales en el archivo solo si funcionan
-    let path = std::path::Path::new("channels/all_channels.m3u8");
-    
-    // Leer canales existentes para evitar duplicados
-    let mut existing_urls = std::collections::HashSet::new();
-    
-    if path.exists() {
-        if let Ok(file) = std::fs::File::open(path) {
-            let reader = std::io::BufReader::new(file);
-            let mut is_url_line = false;
-            
-            for line in reader.lines() {
-                if let Ok(line) = line {
-                    if is_url_line {
-                        existing_urls.insert(line);
-                        is_url_line = false;
-                    } else if line.starts_with("#EXTINF:") {
-                        is_url_line = true;
-                    }
-                }
-            }
-        }
-    }
-    
-    // Filtrar canales duplicados
-    let new_channels: Vec<_> = working_channels.iter()
-        .filter(|(_, url)| !existing_urls.contains(url))
+    let working_channels: Vec<Channel> = checked_channels.into_iter()
+        .filter(|channel| channel.is_working() == Some(true))
         .collect();
     
-    if new_channels.is_empty() {
-        return;
-    }
+    println!("De {} canales en {}, {} están funcionando", channels_count, server, working_channels.len());
     
-    // Guardar nuevos canales
-    if let Ok(file) = std::fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(path) {
-        
-        let mut writer = std::io::BufWriter::new(file);
-        
-        // Añadir cabecera si el archivo es nuevo
-        if !path.exists() || std::fs::metadata(path).map(|m| m.len()).unwrap_or(0) == 0 {
-            if let Err(_) = writeln!(writer, "#EXTM3U") {
-                return;
-            }
-        }
-        
-        // Escribir nuevos canales
-        for (title, url) in &new_channels {
-            if writeln!(writer, "{}", title).is_err() ||
-                writeln!(writer, "{}", url).is_err() {
-                return;
-            }
+    // Guardar en la playlist solo los canales que funcionan y no estaban ya
+    match catalog.append(working_channels).await {
+        Ok(0) => {}
+        Ok(new_channels_count) => {
+            // Incrementar el contador global
+            TOTAL_CHANNELS_FOUND.fetch_add(new_channels_count, Ordering::SeqCst);
+            
+            println!("Server {}: Added {} new channels (Total: {})", 
+                server, new_channels_count, TOTAL_CHANNELS_FOUND.load(Ordering::SeqCst));
         }
-        
-        // Finalizar escritura
-        let _ = writer.flush();
-        
-        // Actualizar contador global atómicamente
-        let new_channels_count = new_channels.len();
-        
-        // Incrementar el contador global
-        TOTAL_CHANNELS_FOUND.fetch_add(new_channels_count, Ordering::SeqCst);
-        
-        println!("Server {}: Added {} new channels (Total: {})", 
-            server, new_channels_count, TOTAL_CHANNELS_FOUND.load(Ordering::SeqCst));
+        Err(e) => eprintln!("Error guardando canales de {}: {}", server, e),
     }
 }
 
 /// Guarda un resumen del escaneo
-async fn save_scan_summary(total_checked: usize, servers_found: usize) -> Result<(), std::io::Error> {
+async fn save_scan_summary(total_checked: usize, servers_found: usize, servers: &[std::net::SocketAddr], source_address: Option<std::net::IpAddr>) -> Result<(), std::io::Error> {
     use chrono::Local;
     use serde_json::json;
     
     let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
     
-    // Leer servidores encontrados del archivo
-    let mut found_servers = Vec::new();
-    if let Ok(file) = std::fs::File::open("found_servers.txt") {
-        let reader = std::io::BufReader::new(file);
-        for line in reader.lines() {
-            if let Ok(server) = line {
-                if !server.trim().is_empty() {
-                    found_servers.push(server);
-                }
-            }
-        }
-    }
+    // Servidores encontrados en este escaneo
+    let found_servers: Vec<String> = servers.iter()
+        .map(|addr| addr.to_string())
+        .collect();
     
     // Obtener cantidad de canales encontrados
     let channels_found = TOTAL_CHANNELS_FOUND.load(Ordering::SeqCst);
@@ -1133,6 +1245,7 @@ async fn save_scan_summary(total_checked: usize, servers_found: usize) -> Result
     // Crear resumen
     let summary = json!({
         "scan_date": timestamp,
+        "source_address": source_address.map(|ip| ip.to_string()),
         "total_checked": total_checked,
         "servers_found": servers_found,
         "channels_found": channels_found,
@@ -1146,58 +1259,57 @@ async fn save_scan_summary(total_checked: usize, servers_found: usize) -> Result
     Ok(())
 }
 
-/// Verifica si un canal está funcionando
-async fn check_channel_working(client: &reqwest::Client, url: &str, timeout: Duration) -> bool {
-    // Primero intentar con un HEAD request (más rápido)
-    match client.head(url)
-        .timeout(timeout)
-        .header(reqwest::header::USER_AGENT, "Mozilla/5.0")
-        .send()
+/// Pide una playlist local y verifica cada una de sus entradas; `None` si se cancela
+async fn pick_and_verify_playlist(config: Config) -> Result<Option<FileVerification>, String> {
+    let file = match rfd::AsyncFileDialog::new()
+        .set_title("Verificar playlist")
+        .add_filter("Playlist M3U", &["m3u", "m3u8"])
+        .pick_file()
         .await {
-        Ok(response) if response.status().is_success() => {
-            return true;
-        },
-        _ => {
-            // Si el HEAD falla y no es un m3u8, intentar obtener un fragmento del stream
-            if !url.to_lowercase().contains(".m3u8") {
-                match client.get(url)
-                    .timeout(timeout)
-                    .header(reqwest::header::USER_AGENT, "Mozilla/5.0")
-                    .send()
-                    .await {
-                    Ok(response) if response.status().is_success() => {
-                        // Intentar leer un fragmento de datos para verificar que el stream funciona
-                        match response.bytes().await {
-                            Ok(bytes) if !bytes.is_empty() => {
-                                return true;
-                            },
-                            _ => return false
-                        }
-                    },
-                    _ => return false
-                }
-            }
-            return false;
-        }
-    }
+        Some(file) => file,
+        None => return Ok(None),
+    };
+    
+    verify::verify_file(file.path(), &config).await
+        .map(Some)
+        .map_err(|e| format!("{:#}", e))
 }
 
-/// Extrae el título del canal de la línea #EXTINF
-fn extract_channel_title(extinf_line: &str) -> String {
-    if let Some(idx) = extinf_line.find(',') {
-        if idx + 1 < extinf_line.len() {
-            return extinf_line[idx + 1..].trim().to_string();
-        }
-    }
-    "Canal sin nombre".to_string()
+/// Lee la guía EPG (XMLTV) de un archivo local
+async fn load_guide(path: String) -> Result<Arc<Guide>, String> {
+    tokio::task::spawn_blocking(move || epg::Guide::load(std::path::Path::new(&path)))
+        .await
+        .map_err(|e| e.to_string())?
+        .map(Arc::new)
+        .map_err(|e| format!("{:#}", e))
+}
+
+/// Lee las versiones guardadas de la playlist de un servidor y compara la
+/// elegida (o la penúltima) con la última
+async fn load_playlist_history(server: std::net::SocketAddr, compared: Option<String>) -> Arc<PlaylistHistory> {
+    let task_compared = compared.clone();
+    tokio::task::spawn_blocking(move || {
+        let snapshots = snapshot::list(server);
+        let changes = snapshot::select(server, task_compared.as_deref(), None)
+            .and_then(|(from, to)| Ok((snapshot::diff(&from.channels()?, &to.channels()?), from, to)))
+            .map_err(|e| format!("{:#}", e));
+        PlaylistHistory { server, compared: task_compared, snapshots, changes }
+    })
+    .await
+    .map(Arc::new)
+    .unwrap_or_else(|e| Arc::new(PlaylistHistory { server, compared, snapshots: Vec::new(), changes: Err(e.to_string()) }))
 }
 
 /// Obtiene el país asociado a una dirección IP
-async fn get_ip_country(ip: &std::net::IpAddr) -> Result<String, String> {
+async fn get_ip_country(probe: crate::config::ProbeConfig, ip: std::net::IpAddr) -> Result<String, String> {
     // Usar el servicio ipapi.co para obtener información geográfica
     let url = format!("https://ipapi.co/{}/json/", ip);
     
-    match reqwest::get(&url).await {
+    let client = http::profile_builder(&probe)
+        .and_then(|builder| Ok(builder.build()?))
+        .map_err(|e| format!("Error creando cliente HTTP: {}", e))?;
+    
+    match client.get(&url).send().await {
         Ok(response) => {
             if response.status().is_success() {
                 match response.json::<serde_json::Value>().await {
@@ -1217,41 +1329,29 @@ async fn get_ip_country(ip: &std::net::IpAddr) -> Result<String, String> {
     }
 }
 
-/// Descarga la playlist de un servidor Astra
-async fn download_server_playlist(ip: &std::net::IpAddr, port: u16) -> Result<(), String> {
-    // URL de la playlist
-    let playlist_url = format!("http://{}:{}/playlist.m3u", ip, port);
-    
+/// Descarga la playlist de un servidor y devuelve la ruta que la sirvió
+async fn download_server_playlist(config: Config, base_url: String, playlist_paths: Vec<String>, protection: Option<playlist::Protection>, ip: std::net::IpAddr, port: u16) -> Result<String, String> {
     // Crear cliente HTTP
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(5))
-        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
-        .build()
+    let client = http::client_builder(&config, std::time::Duration::from_secs(5))
+        .and_then(|builder| Ok(builder.build()?))
         .map_err(|e| format!("Error creando cliente HTTP: {}", e))?;
     
-    // Obtener la playlist
-    let response = client.get(&playlist_url)
-        .send()
-        .await
-        .map_err(|e| format!("Error al conectar con el servidor: {}", e))?;
+    // Obtener la playlist de la primera ruta que la sirva, con el tamaño acotado
+    let playlist = playlist::fetch(&client, &base_url, &playlist_paths, protection.as_ref(), &config.scanner).await
+        .map_err(|e| format!("{:#}", e))?;
     
-    if !response.status().is_success() {
-        return Err(format!("Error al obtener la playlist: {}", response.status()));
-    }
+    // Guardar la versión descargada en el historial de la playlist
+    snapshot::save(std::net::SocketAddr::new(ip, port), &playlist)
+        .map_err(|e| format!("Error guardando la instantánea de la playlist: {}", e))?;
     
-    // Obtener el contenido de la playlist
-    let content = response.text().await
-        .map_err(|e| format!("Error al leer el contenido de la playlist: {}", e))?;
-    
-    if !content.contains("#EXTM3U") {
-        return Err("El contenido descargado no es una playlist M3U válida".to_string());
-    }
+    // URLs absolutas para que la playlist funcione fuera del servidor
+    let content = playlist.with_absolute_urls();
     
     // Crear el directorio de playlists si no existe
     std::fs::create_dir_all("playlists")
         .map_err(|e| format!("Error creando directorio playlists: {}", e))?;
     
-    // Guardar la playlist en el archivo
+    // Guardar la última versión en el archivo; las anteriores quedan en las instantáneas
     let filename = format!("playlists/playlist_{}_{}.m3u", ip, port);
     std::fs::write(&filename, content)
         .map_err(|e| format!("Error guardando la playlist: {}", e))?;
@@ -1275,5 +1375,5 @@ async fn download_server_playlist(ip: &std::net::IpAddr, port: u16) -> Result<()
         .spawn()
         .ok();
     
-    Ok(())
+    Ok(playlist.path)
 } 
\ No newline at end of file
@@ -1,6 +1,9 @@
 use std::net::IpAddr;
-use crate::gui::app::{View, ResultsView};
+use std::sync::Arc;
+use crate::gui::app::{View, ResultsView, PlaylistHistory};
 use crate::config::Config;
+use crate::epg::Guide;
+use crate::verify::FileVerification;
 
 /// Mensajes para la aplicación Iced
 #[derive(Debug, Clone)]
@@ -35,6 +38,7 @@ pub enum Message {
     ConnectionTimeoutChanged(f64),
     PlaylistTimeoutChanged(usize),
     ChannelTimeoutChanged(usize),
+    ConfidenceThresholdChanged(u8),
     PoolConnectionsChanged(usize),
     PoolMaxSizeChanged(usize),
     
@@ -44,15 +48,27 @@ pub enum Message {
     
     // Server interactions
     ViewServerDetails(IpAddr, u16),
+    CloseServerDetails,
+    CompareSnapshot(String),
+    PlaylistHistoryLoaded(Arc<PlaylistHistory>),
     ExportResults,
+    VerifyPlaylistFile,
+    PlaylistFileVerified(Result<Option<FileVerification>, String>),
     PlayChannel(String),
+    ViewChannelDetails(String),
+    CloseChannelDetails,
+    GuideLoaded(Result<Arc<Guide>, String>),
     DownloadServerPlaylist(IpAddr, u16),
+    ServerPlaylistDownloaded(IpAddr, u16, Result<String, String>),
     ShowServerCountry(IpAddr),
     
     // Input changes
     TargetChanged(String),
     PortsChanged(String),
     ChannelsSearchChanged(String),
+    ServersSearchChanged(String),
+    ToggleChannelGroup(String),
+    SetChannelGroupsExpanded(bool),
     
     // Modal actions
     OpenSettings,
@@ -74,7 +90,7 @@ pub enum Message {
     
     // Mensajes de acción
     StartAsnLookup(String),
-    UpdateConfig(Config),
+    UpdateConfig(Box<Config>),
     ToggleLog(String),
     UpdateStatus(String),
     
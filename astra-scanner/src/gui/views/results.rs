@@ -8,14 +8,19 @@ use iced::{
 };
 
 use crate::gui::{
-    app::{AstraApp, ResultsView},
+    app::{AstraApp, PlaylistHistory, ResultsView},
     message::Message,
     style,
 };
+use crate::catalog::{self, ChannelGroup};
+use crate::channel::Channel;
+use crate::epg::{self, Guide, MatchKind};
+use crate::m3u;
+use crate::playlist::PlaylistAccess;
+use crate::scanner::Server;
+use crate::store;
 
 use std::path::Path;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
 
 pub fn view(app: &AstraApp) -> Element<'_, Message> {
     let results_view = app.get_results_view();
@@ -27,6 +32,12 @@ pub fn view(app: &AstraApp) -> Element<'_, Message> {
         row![
             text("Resultados del Escaneo").size(28).style(iced::theme::Text::Default),
             horizontal_space(Length::Fill),
+            button(
+                text("Verificar Playlist").size(16)
+            )
+            .padding([10, 20])
+            .style(iced::theme::Button::Secondary)
+            .on_press(Message::VerifyPlaylistFile),
             button(
                 text("Exportar Resultados").size(16)
             )
@@ -190,12 +201,34 @@ fn view_servers(app: &AstraApp) -> Element<'_, Message> {
         .height(Length::Fill)
         .into()
     } else {
+        // Filtro por título, hashes, encabezados, versión o dirección
+        let search_query = app.get_servers_search();
+        let search_input = container(
+            row![
+                text("Filtrar servidores:").size(16),
+                horizontal_space(Length::Fixed(15.0)),
+                text_input("Título, hash de favicon/cuerpo/certificado, Server, X-Powered-By...", search_query)
+                    .padding(10)
+                    .on_input(Message::ServersSearchChanged)
+                    .width(Length::FillPortion(4)),
+            ]
+            .spacing(10)
+            .align_items(alignment::Alignment::Center)
+        )
+        .padding(15)
+        .width(Length::Fill)
+        .style(iced::theme::Container::Custom(Box::new(style::InfoContainer)));
+        
         // Tabla de servidores con cabecera
         let header_row = container(
             row![
                 text("Dirección IP").size(16).width(Length::FillPortion(3)),
                 text("Puerto").size(16).width(Length::FillPortion(1)),
                 text("Servicio").size(16).width(Length::FillPortion(2)),
+                text("Confianza").size(16).width(Length::FillPortion(2)),
+                text("Versión").size(16).width(Length::FillPortion(3)),
+                text("Certificado TLS").size(16).width(Length::FillPortion(4)),
+                text("Web").size(16).width(Length::FillPortion(4)),
                 text("Descubierto").size(16).width(Length::FillPortion(3)),
                 text("Acciones").size(16).width(Length::FillPortion(3)),
             ]
@@ -208,12 +241,16 @@ fn view_servers(app: &AstraApp) -> Element<'_, Message> {
         // Construir filas de servidores
         let mut server_rows = column![header_row].spacing(5);
         
-        for server in servers {
+        for server in servers.iter().filter(|server| server.matches(search_query)) {
             let row = container(
                 row![
                     text(server.ip.to_string()).size(14).width(Length::FillPortion(3)),
                     text(server.port.to_string()).size(14).width(Length::FillPortion(1)),
                     text(&server.service).size(14).width(Length::FillPortion(2)),
+                    view_confidence(server),
+                    text(server.version_label()).size(14).width(Length::FillPortion(3)),
+                    view_certificate(server),
+                    view_web(server),
                     text(server.discovery_time.format("%Y-%m-%d %H:%M:%S").to_string()).size(14).width(Length::FillPortion(3)),
                     row![
                         // Botón de detalles
@@ -256,14 +293,249 @@ fn view_servers(app: &AstraApp) -> Element<'_, Message> {
         }
         
         // Contenedor con scroll para los resultados
-        scrollable(
-            container(server_rows)
+        let mut content = column![search_input].spacing(10);
+        
+        // Panel de detalles del servidor seleccionado
+        if let Some(server) = app.get_selected_server() {
+            content = content.push(view_server_details(server, app.get_playlist_history()));
+        }
+        
+        content
+            .push(
+                scrollable(
+                    container(server_rows)
+                        .width(Length::Fill)
+                        .padding(10)
+                )
+                .height(Length::Fill)
                 .width(Length::Fill)
-                .padding(10)
-        )
-        .height(Length::Fill)
+            )
+            .into()
+    }
+}
+
+// Resumen del certificado TLS de un servidor
+fn view_certificate(server: &Server) -> Element<'_, Message> {
+    match &server.tls {
+        Some(cert) => {
+            let validity = match (cert.not_before, cert.not_after) {
+                (Some(not_before), Some(not_after)) => format!(
+                    "{} → {}", not_before.format("%Y-%m-%d"), not_after.format("%Y-%m-%d")
+                ),
+                _ => "Validez desconocida".to_string(),
+            };
+            
+            let mut details = column![
+                text(&cert.subject).size(13),
+                text(format!("Emisor: {}", cert.issuer)).size(12),
+                text(validity).size(12).style(iced::theme::Text::Color(
+                    if cert.is_expired() { style::ACCENT_RED } else { style::ACCENT_GREEN }
+                )),
+                text(format!("SHA-256: {}…", cert.sha256.get(..16).unwrap_or(&cert.sha256))).size(12),
+            ]
+            .spacing(2);
+            
+            if !cert.sans.is_empty() {
+                details = details.push(text(format!("SAN: {}", cert.sans.join(", "))).size(12));
+            }
+            
+            details.width(Length::FillPortion(4)).into()
+        }
+        None => text("—").size(14).width(Length::FillPortion(4)).into(),
+    }
+}
+
+// Panel con la respuesta HTTP completa de la sonda que identificó al servidor
+fn view_server_details<'a>(server: &'a Server, history: Option<&PlaylistHistory>) -> Element<'a, Message> {
+    let title = row![
+        text(format!("Detalles de {} ({})", server.addr(), server.version_label())).size(18),
+        horizontal_space(Length::Fill),
+        button(text("Cerrar").size(14))
+            .padding([6, 12])
+            .style(iced::theme::Button::Secondary)
+            .on_press(Message::CloseServerDetails),
+    ]
+    .align_items(alignment::Alignment::Center);
+    
+    let body: Element<'_, Message> = match &server.response {
+        Some(meta) => {
+            let summary = column![
+                text(&meta.status_line).size(15).style(iced::theme::Text::Color(style::ACCENT_BLUE)),
+                text(format!("URL: {}", meta.url)).size(13),
+                text(format!("Content-Type: {}", meta.content_type.as_deref().unwrap_or("—"))).size(13),
+                text(format!("Content-Length: {}", meta.content_length.map(|length| length.to_string()).unwrap_or_else(|| "—".to_string()))).size(13),
+                text(format!("Tiempo hasta cabeceras: {} ms · Latencia total: {} ms", meta.ttfb_ms, meta.latency_ms)).size(13),
+                text(format!("Playlist: {}", playlist_access_label(server))).size(13),
+            ]
+            .spacing(4);
+            
+            let mut headers = column![text("Encabezados").size(14)].spacing(2);
+            for (name, value) in &meta.headers {
+                headers = headers.push(text(format!("{}: {}", name, value)).size(12));
+            }
+            
+            row![
+                summary.width(Length::FillPortion(1)),
+                scrollable(headers).height(Length::Fixed(140.0)).width(Length::FillPortion(1)),
+            ]
+            .spacing(20)
+            .into()
+        }
+        None => text("No hay datos de respuesta para este servidor").size(14).into(),
+    };
+    
+    container(column![title, Rule::horizontal(1), body, Rule::horizontal(1), view_playlist_history(history)].spacing(10))
+        .padding(15)
         .width(Length::Fill)
+        .style(iced::theme::Container::Custom(Box::new(style::CardContainer)))
         .into()
+}
+
+// Versiones guardadas de la playlist y cambios de canales entre una de ellas y la última
+fn view_playlist_history<'a>(history: Option<&PlaylistHistory>) -> Element<'a, Message> {
+    let history = match history {
+        Some(history) => history,
+        None => return text("Leyendo las versiones de la playlist…").size(13).into(),
+    };
+    let snapshots = &history.snapshots;
+    let latest = match snapshots.last() {
+        Some(latest) => latest,
+        None => return text("No hay versiones guardadas de la playlist").size(13).into(),
+    };
+    
+    // Lista de versiones, la más reciente primero, con el botón para compararlas con la última
+    let mut versions = column![text(format!("Versiones de la playlist ({})", snapshots.len())).size(14)].spacing(4);
+    for snapshot in snapshots.iter().rev() {
+        let id = snapshot.id();
+        let mut version = row![
+            text(format!("{} · {} entradas · {} · {}",
+                snapshot.captured_at.format("%d/%m/%Y %H:%M"),
+                snapshot.entries,
+                snapshot.encoding.as_deref().unwrap_or("codificación desconocida"),
+                snapshot.sha256.get(..12).unwrap_or(&snapshot.sha256)
+            )).size(12),
+            horizontal_space(Length::Fill),
+        ]
+        .align_items(alignment::Alignment::Center);
+        if id != latest.id() {
+            version = version.push(
+                button(text("Comparar").size(12))
+                    .padding([4, 8])
+                    .style(iced::theme::Button::Secondary)
+                    .on_press(Message::CompareSnapshot(id))
+            );
+        }
+        versions = versions.push(version);
+    }
+    
+    // Cambios entre la versión elegida (o la penúltima) y la última
+    let changes: Element<'a, Message> = match &history.changes {
+        Ok((diff, from, to)) => {
+            let mut lines = column![
+                text(format!("Del {} al {}: {} añadidos, {} eliminados, {} renombrados, {} movidos",
+                    from.captured_at.format("%d/%m %H:%M"), to.captured_at.format("%d/%m %H:%M"),
+                    diff.added.len(), diff.removed.len(), diff.renamed.len(), diff.moved.len())).size(13),
+            ]
+            .spacing(2);
+            
+            if diff.is_empty() {
+                lines = lines.push(text("Sin cambios en los canales").size(12));
+            }
+            for channel in &diff.added {
+                lines = lines.push(text(format!("+ {}", channel.name)).size(12).style(iced::theme::Text::Color(style::ACCENT_GREEN)));
+            }
+            for channel in &diff.removed {
+                lines = lines.push(text(format!("- {}", channel.name)).size(12).style(iced::theme::Text::Color(style::ACCENT_RED)));
+            }
+            for (before, after) in &diff.renamed {
+                lines = lines.push(text(format!("~ {} → {}", before.name, after.name)).size(12).style(iced::theme::Text::Color(style::ACCENT_BLUE)));
+            }
+            for (before, after) in &diff.moved {
+                lines = lines.push(text(format!("> {}: {} → {}", after.name,
+                    before.group.as_deref().unwrap_or("sin grupo"), after.group.as_deref().unwrap_or("sin grupo"))).size(12));
+            }
+            lines.into()
+        }
+        Err(_) if snapshots.len() == 1 => text("Solo hay una versión guardada").size(12).into(),
+        Err(e) => text(format!("No se pudieron comparar las versiones: {}", e)).size(12).style(iced::theme::Text::Color(style::ACCENT_RED)).into(),
+    };
+    
+    row![
+        scrollable(versions).height(Length::Fixed(140.0)).width(Length::FillPortion(1)),
+        scrollable(changes).height(Length::Fixed(140.0)).width(Length::FillPortion(1)),
+    ]
+    .spacing(20)
+    .into()
+}
+
+// Estado de la playlist: abierta, protegida (esquema y realm) o no disponible
+fn playlist_access_label(server: &Server) -> String {
+    let path = server.playlist_path.as_deref().unwrap_or("—");
+    match &server.playlist_access {
+        Some(PlaylistAccess::Open) => format!("abierta en {}", path),
+        Some(PlaylistAccess::Protected(protection)) => {
+            let mut label = format!("protegida en {} ({}", protection.path, protection.status);
+            if let Some(scheme) = &protection.scheme {
+                label = format!("{}, {}", label, scheme);
+            }
+            if let Some(realm) = &protection.realm {
+                label = format!("{}, realm \"{}\"", label, realm);
+            }
+            format!("{})", label)
+        }
+        Some(PlaylistAccess::Unavailable) => "no disponible".to_string(),
+        None => "sin comprobar".to_string(),
+    }
+}
+
+// Puntuación de confianza y resultado de la sonda de confirmación
+fn view_confidence(server: &Server) -> Element<'_, Message> {
+    let detection = match &server.detection {
+        Some(detection) => detection,
+        None => return text("—").size(14).width(Length::FillPortion(2)).into(),
+    };
+    
+    let (label, color) = if server.confirmed {
+        (format!("{}%", detection.confidence), style::ACCENT_GREEN)
+    } else {
+        (format!("{}% sin confirmar", detection.confidence), style::ACCENT_RED)
+    };
+    
+    let mut details = column![
+        text(label).size(14).style(iced::theme::Text::Color(color)),
+    ]
+    .spacing(2);
+    
+    if let Some(confirmation) = &detection.confirmation {
+        details = details.push(text(&confirmation.evidence).size(12));
+    }
+    
+    details.width(Length::FillPortion(2)).into()
+}
+
+// Título, encabezados y hashes de la interfaz web de un servidor
+fn view_web(server: &Server) -> Element<'_, Message> {
+    match &server.web {
+        Some(web) => {
+            let mut details = column![
+                text(web.title.as_deref().unwrap_or("Sin título")).size(13),
+            ]
+            .spacing(2);
+            
+            let headers: Vec<&str> = [&web.server, &web.powered_by].into_iter().flatten().map(String::as_str).collect();
+            if !headers.is_empty() {
+                details = details.push(text(headers.join(" · ")).size(12));
+            }
+            if let Some(hash) = web.favicon_mmh3 {
+                details = details.push(text(format!("Favicon: {}", hash)).size(12));
+            }
+            if let Some(hash) = web.body_mmh3 {
+                details = details.push(text(format!("Cuerpo: {}", hash)).size(12));
+            }
+            
+            details.width(Length::FillPortion(4)).into()
+        }
+        None => text("—").size(14).width(Length::FillPortion(4)).into(),
     }
 }
 
@@ -284,6 +556,10 @@ fn view_channels(app: &AstraApp) -> Element<'_, Message> {
                 .padding(10)
                 .on_input(Message::ChannelsSearchChanged)
                 .width(Length::FillPortion(4)),
+            button(text(if app.channel_groups_expanded() { "Contraer fuentes" } else { "Expandir fuentes" }).size(14))
+                .padding([10, 15])
+                .style(iced::theme::Button::Secondary)
+                .on_press(Message::SetChannelGroupsExpanded(!app.channel_groups_expanded())),
         ]
         .spacing(10)
         .align_items(alignment::Alignment::Center)
@@ -326,27 +602,40 @@ fn view_channels(app: &AstraApp) -> Element<'_, Message> {
         .height(Length::Fill)
         .into()
     } else {
-        // Filtrar canales según la búsqueda
-        let filtered_channels: Vec<_> = if search_query.is_empty() {
-            all_channels
-        } else {
-            all_channels.into_iter()
-                .filter(|(title, _)| {
-                    extract_channel_title(title)
-                        .to_lowercase()
-                        .contains(&search_query.to_lowercase())
-                })
-                .collect()
-        };
+        // Filtrar canales según la búsqueda (nombre o grupo) y juntar las
+        // fuentes del mismo canal de distintos servidores
+        let selected_channel = app.get_selected_channel()
+            .and_then(|url| all_channels.iter().find(|channel| channel.url == url))
+            .cloned();
+        let filtered_channels: Vec<Channel> = all_channels.into_iter()
+            .filter(|channel| channel.matches(search_query))
+            .collect();
+        let sources_count = filtered_channels.len();
+        let channel_groups = catalog::group(filtered_channels);
         
         // Mostrar resultados de búsqueda
         let search_results_text = if !search_query.is_empty() {
-            format!("Mostrando {} canales que coinciden con '{}'", 
-                filtered_channels.len(),
+            format!("Mostrando {} canales ({} fuentes) que coinciden con '{}'", 
+                channel_groups.len(),
+                sources_count,
                 search_query
             )
         } else {
-            format!("Mostrando todos los canales ({})", filtered_channels.len())
+            format!("Mostrando todos los canales ({}, {} fuentes)", channel_groups.len(), sources_count)
+        };
+        
+        // Cobertura de la guía EPG sobre los canales mostrados
+        let search_results_text = match app.get_guide() {
+            Some(guide) => {
+                let coverage = epg::coverage(guide, &channel_groups);
+                format!("{} · Guía EPG: {} de {} canales ({:.1}%)",
+                    search_results_text,
+                    coverage.matched(),
+                    coverage.channels,
+                    coverage.percent()
+                )
+            }
+            None => search_results_text,
         };
         
         let search_results_info = container(
@@ -358,9 +647,11 @@ fn view_channels(app: &AstraApp) -> Element<'_, Message> {
         // Tabla de canales con cabecera
         let header_row = container(
             row![
-                text("Nombre del Canal").size(16).width(Length::FillPortion(5)),
-                text("URL").size(16).width(Length::FillPortion(6)),
-                text("Acciones").size(16).width(Length::FillPortion(1)),
+                text("Nombre del Canal").size(16).width(Length::FillPortion(4)),
+                text("Grupo").size(16).width(Length::FillPortion(2)),
+                text("URL").size(16).width(Length::FillPortion(5)),
+                text("Estado").size(16).width(Length::FillPortion(2)),
+                text("Acciones").size(16).width(Length::FillPortion(2)),
             ]
             .spacing(10)
             .padding(15)
@@ -371,38 +662,37 @@ fn view_channels(app: &AstraApp) -> Element<'_, Message> {
         // Construir filas de canales
         let mut channel_rows = column![header_row].spacing(5);
         
-        for (title, url) in filtered_channels {
-            let channel_title = extract_channel_title(&title);
-            
-            let row = container(
-                row![
-                    text(channel_title).size(14).width(Length::FillPortion(5)),
-                    text(&url).size(14).width(Length::FillPortion(6)),
-                    button(
-                        row![
-                            text("▶").size(16).style(iced::theme::Text::Color(style::ACCENT_GREEN))
-                        ]
-                    )
-                    .padding([8, 12])
-                    .style(iced::theme::Button::Primary)
-                    .on_press(Message::PlayChannel(url.clone()))
-                    .width(Length::FillPortion(1)),
-                ]
-                .spacing(10)
-                .padding(15)
-                .align_items(alignment::Alignment::Center)
-            )
-            .style(iced::theme::Container::Custom(Box::new(style::CardContainer)))
-            .width(Length::Fill);
+        for group in &channel_groups {
+            // Un canal con una sola fuente se muestra como una fila normal
+            if group.sources.len() == 1 {
+                channel_rows = channel_rows.push(view_channel_row(&group.sources[0], false));
+                continue;
+            }
             
-            channel_rows = channel_rows.push(row);
+            let expanded = app.is_channel_group_expanded(&group.key);
+            channel_rows = channel_rows.push(view_channel_group_row(group, expanded));
+            if expanded {
+                for source in &group.sources {
+                    channel_rows = channel_rows.push(view_channel_row(source, true));
+                }
+            }
         }
         
+        // Panel de detalles del canal seleccionado, con su programación
+        let details: Element<'_, Message> = match &selected_channel {
+            Some(channel) => column![
+                vertical_space(Length::Fixed(10.0)),
+                view_channel_details(channel, app.get_guide()),
+            ].into(),
+            None => column![].into(),
+        };
+        
         // Contenedor con scroll para los resultados
         column![
             search_input,
             vertical_space(Length::Fixed(15.0)),
             search_results_info,
+            details,
             vertical_space(Length::Fixed(10.0)),
             scrollable(
                 container(channel_rows)
@@ -419,43 +709,216 @@ fn view_channels(app: &AstraApp) -> Element<'_, Message> {
     }
 }
 
-// Función para leer los canales del archivo
-fn read_channels_from_file() -> Vec<(String, String)> {
+// Función para leer los canales: los registros con verificación y fechas, o
+// la playlist si aún no hay registros
+fn read_channels_from_file() -> Vec<Channel> {
+    let records = store::load_channel_records();
+    if !records.is_empty() {
+        return records.into_iter()
+            .filter(|channel| channel.is_working() != Some(false))
+            .collect();
+    }
+    
     let path = Path::new("channels/all_channels.m3u8");
     
     if !path.exists() {
         return Vec::new();
     }
     
-    match File::open(path) {
-        Ok(file) => {
-            let reader = BufReader::new(file);
-            let mut channels = Vec::new();
-            let mut current_title = String::new();
-            
-            for line in reader.lines() {
-                if let Ok(line) = line {
-                    if line.starts_with("#EXTINF:") {
-                        current_title = line;
-                    } else if (line.starts_with("http://") || line.starts_with("https://")) && !current_title.is_empty() {
-                        channels.push((current_title.clone(), line));
-                        current_title = String::new();
-                    }
-                }
-            }
-            
-            channels
-        },
+    match m3u::parse_file(path) {
+        Ok(document) => document.entries.iter()
+            .map(|entry| Channel::from_entry(entry, entry.uri.trim(), None))
+            .collect(),
         Err(_) => Vec::new(),
     }
 }
 
-// Función para extraer el título del canal de la línea #EXTINF
-fn extract_channel_title(extinf_line: &str) -> String {
-    if let Some(idx) = extinf_line.find(',') {
-        if idx + 1 < extinf_line.len() {
-            return extinf_line[idx + 1..].trim().to_string();
+// Fila de un canal; las fuentes de un grupo abierto van sangradas y con su servidor
+fn view_channel_row<'a>(channel: &Channel, nested: bool) -> Element<'a, Message> {
+    let url = channel.url.clone();
+    
+    let mut name = column![text(&channel.name).size(14)];
+    if nested {
+        name = name.push(text(channel.server.as_deref().unwrap_or("servidor desconocido")).size(11));
+    }
+    
+    let row = container(
+        row![
+            name.width(Length::FillPortion(4)),
+            text(channel.group.as_deref().unwrap_or("—")).size(14).width(Length::FillPortion(2)),
+            text(&url).size(14).width(Length::FillPortion(5)),
+            view_channel_status(channel),
+            row![
+                button(
+                    row![
+                        text("▶").size(16).style(iced::theme::Text::Color(style::ACCENT_GREEN))
+                    ]
+                )
+                .padding([8, 12])
+                .style(iced::theme::Button::Primary)
+                .on_press(Message::PlayChannel(url.clone())),
+                button(text("ℹ").size(16))
+                    .padding([8, 12])
+                    .style(iced::theme::Button::Secondary)
+                    .on_press(Message::ViewChannelDetails(url)),
+            ]
+            .spacing(5)
+            .width(Length::FillPortion(2)),
+        ]
+        .spacing(10)
+        .padding(15)
+        .align_items(alignment::Alignment::Center)
+    )
+    .style(iced::theme::Container::Custom(Box::new(style::CardContainer)))
+    .width(Length::Fill);
+    
+    if nested {
+        container(row).padding(Padding::from([0, 0, 0, 40])).into()
+    } else {
+        row.into()
+    }
+}
+
+// Fila de un canal con varias fuentes, con el botón para abrirlo o cerrarlo
+fn view_channel_group_row<'a>(group: &ChannelGroup, expanded: bool) -> Element<'a, Message> {
+    let working = group.working();
+    let status_color = if working > 0 { style::ACCENT_GREEN } else { style::STATS_LABEL };
+    
+    // Reproducir la primera fuente que funciona, o la primera si no se sabe
+    let play_url = group.sources.iter()
+        .find(|source| source.is_working() == Some(true))
+        .unwrap_or(&group.sources[0])
+        .url
+        .clone();
+    
+    container(
+        row![
+            row![
+                button(text(if expanded { "▾" } else { "▸" }).size(16))
+                    .padding([4, 10])
+                    .style(iced::theme::Button::Secondary)
+                    .on_press(Message::ToggleChannelGroup(group.key.clone())),
+                column![
+                    text(&group.name).size(14),
+                    text(format!("{} fuentes en {} servidores", group.sources.len(), group.servers())).size(11),
+                ],
+            ]
+            .spacing(8)
+            .align_items(alignment::Alignment::Center)
+            .width(Length::FillPortion(4)),
+            text(group.sources[0].group.as_deref().unwrap_or("—")).size(14).width(Length::FillPortion(2)),
+            text(&group.key).size(12).width(Length::FillPortion(5)),
+            text(format!("{}/{} funcionando", working, group.sources.len()))
+                .size(13)
+                .style(iced::theme::Text::Color(status_color))
+                .width(Length::FillPortion(2)),
+            button(
+                row![
+                    text("▶").size(16).style(iced::theme::Text::Color(style::ACCENT_GREEN))
+                ]
+            )
+            .padding([8, 12])
+            .style(iced::theme::Button::Primary)
+            .on_press(Message::PlayChannel(play_url))
+            .width(Length::FillPortion(2)),
+        ]
+        .spacing(10)
+        .padding(15)
+        .align_items(alignment::Alignment::Center)
+    )
+    .style(iced::theme::Container::Custom(Box::new(style::CardContainer)))
+    .width(Length::Fill)
+    .into()
+}
+
+// Detalles de un canal y su programación en la guía EPG, si aparece en ella
+fn view_channel_details<'a>(channel: &Channel, guide: Option<&Guide>) -> Element<'a, Message> {
+    let title = row![
+        text(&channel.name).size(18).style(iced::theme::Text::Color(style::ACCENT_BLUE)),
+        horizontal_space(Length::Fill),
+        button(text("Cerrar").size(14))
+            .padding([6, 12])
+            .style(iced::theme::Button::Secondary)
+            .on_press(Message::CloseChannelDetails),
+    ]
+    .align_items(alignment::Alignment::Center);
+    
+    let info = column![
+        text(format!("URL: {}", channel.url)).size(13),
+        text(format!("Grupo: {}", channel.group.as_deref().unwrap_or("—"))).size(13),
+        text(format!("tvg-id: {}", channel.tvg_id.as_deref().unwrap_or("—"))).size(13),
+        text(format!("Servidor: {}", channel.server.as_deref().unwrap_or("—"))).size(13),
+        text(format!("Visto por primera vez: {} · última: {}",
+            channel.first_seen.format("%d/%m/%Y %H:%M"),
+            channel.last_seen.format("%d/%m/%Y %H:%M")
+        )).size(13),
+    ]
+    .spacing(4)
+    .width(Length::FillPortion(1));
+    
+    let mut programme_list = column![].spacing(4);
+    match guide.map(|guide| (guide, guide.find(channel))) {
+        None => {
+            programme_list = programme_list.push(text("No hay guía EPG cargada (epg.file en pool/config.json)").size(13));
+        }
+        Some((_, None)) => {
+            programme_list = programme_list.push(text("El canal no aparece en la guía EPG").size(13));
+        }
+        Some((guide, Some((guide_channel, kind)))) => {
+            let matched_by = match kind {
+                MatchKind::TvgId => "por tvg-id",
+                MatchKind::Name => "por nombre",
+            };
+            programme_list = programme_list.push(
+                text(format!("Guía: {} ({})", guide_channel.id, matched_by))
+                    .size(13)
+                    .style(iced::theme::Text::Color(style::ACCENT_GREEN))
+            );
+            
+            let mut upcoming = guide.upcoming(&guide_channel.id, chrono::Utc::now()).take(8).peekable();
+            if upcoming.peek().is_none() {
+                programme_list = programme_list.push(text("Sin programación próxima en la guía").size(13));
+            }
+            for programme in upcoming {
+                let start = programme.start
+                    .map(|start| start.with_timezone(&Local).format("%d/%m %H:%M").to_string())
+                    .unwrap_or_else(|| "—".to_string());
+                programme_list = programme_list.push(text(format!("{}  {}", start, programme.title)).size(13));
+            }
         }
     }
-    "Canal sin nombre".to_string()
-} 
\ No newline at end of file
+    
+    container(
+        column![
+            title,
+            Rule::horizontal(1),
+            row![
+                info,
+                scrollable(programme_list).height(Length::Fixed(160.0)).width(Length::FillPortion(1)),
+            ]
+            .spacing(20),
+        ]
+        .spacing(10)
+    )
+    .padding(15)
+    .width(Length::Fill)
+    .style(iced::theme::Container::Custom(Box::new(style::CardContainer)))
+    .into()
+}
+
+// Resultado de la última verificación del canal y cuándo se vio por última vez
+fn view_channel_status<'a>(channel: &Channel) -> Element<'a, Message> {
+    let (label, color) = match &channel.verification {
+        Some(verification) if verification.working => (format!("✓ {}", verification.checked_at.format("%d/%m %H:%M")), style::ACCENT_GREEN),
+        Some(verification) => (format!("✗ {}", verification.checked_at.format("%d/%m %H:%M")), style::ACCENT_RED),
+        None if !channel.is_verifiable() => (format!("no verificable ({})", channel.scheme().unwrap_or_default()), style::STATS_LABEL),
+        None => ("sin verificar".to_string(), style::STATS_LABEL),
+    };
+    
+    column![
+        text(label).size(13).style(iced::theme::Text::Color(color)),
+        text(format!("visto {}", channel.last_seen.format("%d/%m/%Y"))).size(11),
+    ]
+    .width(Length::FillPortion(2))
+    .into()
+}
@@ -186,6 +186,23 @@ pub fn view(app: &AstraApp) -> Element<'_, Message> {
     .padding(5)
     .align_items(alignment::Alignment::Center);
     
+    // Confidence Threshold
+    let confidence_threshold = config.scanner.confidence_threshold;
+    let confidence_threshold_slider = slider(
+        0..=100, 
+        confidence_threshold as i32, 
+        |val| Message::ConfidenceThresholdChanged(val as u8)
+    ).step(5);
+    
+    let confidence_threshold_row = row![
+        text("Confidence Threshold:").size(18).width(Length::FillPortion(2)),
+        confidence_threshold_slider.width(Length::FillPortion(4)),
+        text(format!("{}", confidence_threshold)).size(18).width(Length::FillPortion(1)),
+    ]
+    .spacing(10)
+    .padding(5)
+    .align_items(alignment::Alignment::Center);
+    
     // Pool Connections
     let pool_connections = config.scanner.pool_connections;
     let pool_connections_slider = slider(
@@ -233,6 +250,7 @@ pub fn view(app: &AstraApp) -> Element<'_, Message> {
             connection_timeout_row,
             playlist_timeout_row,
             channel_timeout_row,
+            confidence_threshold_row,
             pool_connections_row,
             pool_maxsize_row,
         ]
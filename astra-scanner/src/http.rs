@@ -0,0 +1,278 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE, COOKIE, FROM, PROXY_AUTHORIZATION};
+use reqwest::redirect::Policy;
+use reqwest::{Client, ClientBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, ProbeConfig, ProbeMethod};
+
+/// Headers that carry credentials or session tokens
+const CREDENTIAL_HEADERS: [HeaderName; 3] = [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE];
+
+/// Redirects followed before giving up
+const MAX_REDIRECTS: usize = 10;
+
+/// Builder identifying itself with the configured probe profile.
+///
+/// Sets the User-Agent, the `From` contact header and any extra headers.
+///
+/// Clients never send credentials: credential headers are refused in the
+/// profile, cookies are not stored, and redirects to URLs with a user name or
+/// password are not followed. A server that asks for authentication gets the
+/// same anonymous request once and is recorded as protected.
+pub fn profile_builder(probe: &ProbeConfig) -> Result<ClientBuilder> {
+    let mut headers = HeaderMap::new();
+
+    if let Some(from) = probe.contact() {
+        headers.insert(FROM, HeaderValue::from_str(from)
+            .with_context(|| format!("Invalid From header: {}", from))?);
+    }
+
+    for (name, value) in &probe.extra_headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("Invalid header name: {}", name))?;
+        if CREDENTIAL_HEADERS.contains(&name) {
+            bail!("Header {} carries credentials, which the scanner never sends", name);
+        }
+        let value = HeaderValue::from_str(value)
+            .with_context(|| format!("Invalid value for header {}", name))?;
+        headers.insert(name, value);
+    }
+
+    Ok(Client::builder()
+        .user_agent(probe.user_agent.as_str())
+        .default_headers(headers)
+        .redirect(anonymous_redirects()))
+}
+
+/// Redirect policy that refuses to carry credentials embedded in a URL
+fn anonymous_redirects() -> Policy {
+    Policy::custom(|attempt| {
+        let url = attempt.url();
+        if !url.username().is_empty() || url.password().is_some() {
+            attempt.stop()
+        } else if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+/// Builder for clients that talk to scan targets.
+///
+/// Carries the probe profile and sends requests from the configured source
+/// address, if any. Certificates are not verified so that TLS servers with
+/// self-signed certificates can still be identified, and the peer certificate
+/// is kept on each response for `CertificateInfo::from_response`.
+pub fn client_builder(config: &Config, timeout: Duration) -> Result<ClientBuilder> {
+    Ok(profile_builder(&config.probe)?
+        .timeout(timeout)
+        .local_address(source_address(config)?)
+        .danger_accept_invalid_certs(true)
+        .tls_info(true))
+}
+
+/// Local address scan traffic is bound to.
+///
+/// `scanner.source_address` may be an IP address or an interface name, in
+/// which case the interface's first IPv4 address is used (or its first
+/// address if it has no IPv4 one).
+pub fn source_address(config: &Config) -> Result<Option<IpAddr>> {
+    let source = match config.scanner.source_address.as_deref().map(str::trim) {
+        Some(source) if !source.is_empty() => source,
+        _ => return Ok(None),
+    };
+
+    if let Ok(ip) = source.parse::<IpAddr>() {
+        return Ok(Some(ip));
+    }
+
+    let addrs: Vec<IpAddr> = if_addrs::get_if_addrs()?
+        .into_iter()
+        .filter(|iface| iface.name == source)
+        .map(|iface| iface.ip())
+        .collect();
+
+    addrs.iter()
+        .find(|ip| ip.is_ipv4())
+        .or_else(|| addrs.first())
+        .copied()
+        .map(Some)
+        .ok_or_else(|| anyhow!("Source address '{}' is not an IP address or a local interface", source))
+}
+
+/// Send a fingerprint probe to `addr`, trying TLS first when `try_tls` is set.
+///
+/// A `HEAD` is retried as `GET` when the server answers 405. Returns the base
+/// URL that answered, its response and the time it took to get the response
+/// headers.
+pub async fn probe(client: &Client, method: ProbeMethod, path: &str, addr: SocketAddr, try_tls: bool, timeout: Duration) -> Option<(String, Response, Duration)> {
+    let schemes: &[&str] = if try_tls { &["https", "http"] } else { &["http"] };
+    let path = if path.starts_with('/') { path.to_string() } else { format!("/{}", path) };
+
+    for scheme in schemes {
+        let base_url = format!("{}://{}", scheme, addr);
+        let url = format!("{}{}", base_url, path);
+
+        let request = match method {
+            ProbeMethod::Head => client.head(&url),
+            ProbeMethod::Get => client.get(&url),
+        };
+
+        let start = Instant::now();
+        let response = match request.timeout(timeout).send().await {
+            Ok(response) if method == ProbeMethod::Head && response.status() == StatusCode::METHOD_NOT_ALLOWED => {
+                client.get(&url).timeout(timeout).send().await
+            }
+            result => result,
+        };
+
+        if let Ok(response) = response {
+            return Some((base_url, response, start.elapsed()));
+        }
+    }
+
+    None
+}
+
+/// What a bounded read got from a response body
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub bytes: Vec<u8>,
+    /// Time from the start of the read to the first body chunk
+    pub first_byte: Option<Duration>,
+    pub elapsed: Duration,
+    /// A byte or time limit stopped the read before the body ended
+    pub truncated: bool,
+}
+
+/// Read at most `max_bytes` of a response body, for at most `max_time`.
+///
+/// Hitting a limit is not an error, since live streams never end: the caller
+/// gets whatever arrived. A transport error is only returned if nothing was
+/// read at all.
+pub async fn read_bounded(mut response: Response, max_bytes: usize, max_time: Duration) -> Result<Sample> {
+    let start = Instant::now();
+    let deadline = tokio::time::Instant::now() + max_time;
+    let mut bytes = Vec::new();
+    let mut first_byte = None;
+    let mut truncated = false;
+
+    // Reading on at the limit tells a body that ends there from a longer one
+    loop {
+        match tokio::time::timeout_at(deadline, response.chunk()).await {
+            Ok(Ok(Some(chunk))) => {
+                first_byte.get_or_insert_with(|| start.elapsed());
+                let room = max_bytes - bytes.len();
+                if chunk.len() > room {
+                    bytes.extend_from_slice(&chunk[..room]);
+                    truncated = true;
+                    break;
+                }
+                bytes.extend_from_slice(&chunk);
+            }
+            Ok(Ok(None)) => break,
+            Ok(Err(e)) if bytes.is_empty() => return Err(e.into()),
+            Ok(Err(_)) | Err(_) => {
+                truncated = true;
+                break;
+            }
+        }
+    }
+
+    Ok(Sample {
+        bytes,
+        first_byte,
+        elapsed: start.elapsed(),
+        truncated,
+    })
+}
+
+/// Status line, headers and timings of a probe response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseMeta {
+    pub url: String,
+    /// e.g. `HTTP/1.1 200 OK`
+    pub status_line: String,
+    /// All headers in the order received, repeated headers included
+    pub headers: Vec<(String, String)>,
+    pub content_type: Option<String>,
+    pub content_length: Option<u64>,
+    /// Time from sending the request to receiving the response headers
+    pub ttfb_ms: u64,
+    /// Time from sending the request to the end of the body sample
+    pub latency_ms: u64,
+}
+
+impl ResponseMeta {
+    /// Metadata of `response`, with the body not yet read
+    pub fn from_response(response: &Response, ttfb: Duration) -> Self {
+        let status = response.status();
+        let headers = response.headers();
+
+        Self {
+            url: response.url().to_string(),
+            status_line: format!("{:?} {} {}", response.version(), status.as_u16(), status.canonical_reason().unwrap_or("")).trim_end().to_string(),
+            headers: headers.iter()
+                .map(|(name, value)| (name.to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned()))
+                .collect(),
+            content_type: headers.get(CONTENT_TYPE).and_then(|value| value.to_str().ok()).map(str::to_string),
+            content_length: response.content_length(),
+            ttfb_ms: ttfb.as_millis() as u64,
+            latency_ms: ttfb.as_millis() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve `body` once over HTTP/1.1 on a local port and return its URL
+    async fn serve(body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = socket.read(&mut request).await;
+            let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(&body).await.unwrap();
+        });
+        format!("http://{}/", addr)
+    }
+
+    async fn read(body: Vec<u8>, max_bytes: usize) -> Sample {
+        let url = serve(body).await;
+        let response = reqwest::get(&url).await.unwrap();
+        read_bounded(response, max_bytes, Duration::from_secs(5)).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn body_ending_at_the_limit_is_complete() {
+        let sample = read(vec![b'x'; 64], 64).await;
+        assert_eq!(sample.bytes.len(), 64);
+        assert!(!sample.truncated);
+    }
+
+    #[tokio::test]
+    async fn body_past_the_limit_is_truncated() {
+        let sample = read(vec![b'x'; 65], 64).await;
+        assert_eq!(sample.bytes.len(), 64);
+        assert!(sample.truncated);
+    }
+
+    #[tokio::test]
+    async fn short_body_is_complete() {
+        let sample = read(b"#EXTM3U\n".to_vec(), 64).await;
+        assert_eq!(sample.bytes, b"#EXTM3U\n");
+        assert!(!sample.truncated);
+        assert!(sample.first_byte.is_some());
+    }
+}
@@ -0,0 +1,717 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+use serde::{Deserialize, Serialize};
+
+/// A parsed M3U playlist.
+///
+/// Parsing never fails: anything unexpected is kept as well as possible and
+/// reported in `warnings` with its line number.
+#[derive(Debug, Clone, Default)]
+pub struct Document {
+    /// The `#EXTM3U` line, if the playlist has one
+    pub header: Option<Header>,
+    pub entries: Vec<Entry>,
+    pub warnings: Vec<Warning>,
+}
+
+/// `#EXTM3U` header with its attributes, e.g. `x-tvg-url`
+#[derive(Debug, Clone, Default)]
+pub struct Header {
+    pub line: usize,
+    pub attributes: Attributes,
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#EXTM3U")?;
+        write_attributes(f, &self.attributes)
+    }
+}
+
+/// One media entry: the URI and the directives that precede it
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// Line of the URI, 1-based
+    pub line: usize,
+    pub extinf: Option<Extinf>,
+    /// `#EXTGRP` group
+    pub group: Option<String>,
+    /// `#EXTVLCOPT` options, e.g. `http-user-agent`
+    pub vlc_options: Attributes,
+    /// `#KODIPROP` properties, e.g. `inputstream.adaptive.license_type`
+    pub kodi_props: Attributes,
+    /// Other directives before the URI, verbatim
+    pub other_tags: Vec<String>,
+    pub uri: String,
+}
+
+impl Entry {
+    /// Display name: the `#EXTINF` title, else `tvg-name`, else the URI
+    pub fn title(&self) -> &str {
+        self.extinf.as_ref()
+            .map(|extinf| extinf.title.as_str())
+            .filter(|title| !title.is_empty())
+            .or_else(|| self.tvg_name())
+            .unwrap_or(&self.uri)
+    }
+
+    /// `group-title` attribute, else the `#EXTGRP` group
+    pub fn group(&self) -> Option<&str> {
+        self.attribute("group-title").or(self.group.as_deref())
+    }
+
+    /// `#EXTINF` attribute, e.g. `tvg-id`
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.extinf.as_ref().and_then(|extinf| extinf.attributes.get(name))
+    }
+
+    pub fn tvg_id(&self) -> Option<&str> {
+        self.attribute("tvg-id")
+    }
+
+    pub fn tvg_name(&self) -> Option<&str> {
+        self.attribute("tvg-name")
+    }
+
+    pub fn tvg_logo(&self) -> Option<&str> {
+        self.attribute("tvg-logo")
+    }
+
+    pub fn catchup(&self) -> Option<&str> {
+        self.attribute("catchup")
+    }
+
+    /// The `#EXTINF` line of this entry, rebuilt if it had none
+    pub fn extinf_line(&self) -> String {
+        match &self.extinf {
+            Some(extinf) => extinf.to_string(),
+            None => Extinf::untitled(self.title()).to_string(),
+        }
+    }
+}
+
+/// The entry's lines: `#EXTINF`, `#EXTGRP`, options, other directives and
+/// the URI, without a trailing newline
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.extinf_line())?;
+        if let Some(group) = &self.group {
+            writeln!(f, "#EXTGRP:{}", one_line(group))?;
+        }
+        for (key, value) in self.vlc_options.iter() {
+            writeln!(f, "#EXTVLCOPT:{}={}", key, one_line(value))?;
+        }
+        for (key, value) in self.kodi_props.iter() {
+            writeln!(f, "#KODIPROP:{}={}", key, one_line(value))?;
+        }
+        for tag in &self.other_tags {
+            writeln!(f, "{}", one_line(tag))?;
+        }
+        write!(f, "{}", one_line(&self.uri))
+    }
+}
+
+/// `#EXTINF:<duration> key="value" ...,<title>`
+#[derive(Debug, Clone)]
+pub struct Extinf {
+    pub line: usize,
+    /// Seconds; -1 for live streams
+    pub duration: f64,
+    pub attributes: Attributes,
+    pub title: String,
+}
+
+impl Extinf {
+    /// Live entry with only a title
+    pub fn untitled(title: &str) -> Self {
+        Self {
+            line: 0,
+            duration: -1.0,
+            attributes: Attributes::default(),
+            title: title.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Extinf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#EXTINF:{}", self.duration)?;
+        write_attributes(f, &self.attributes)?;
+        write!(f, ",{}", one_line(&self.title))
+    }
+}
+
+/// ` key="value"` for each attribute. Quotes inside a value, which the
+/// format cannot escape, become single quotes.
+fn write_attributes(f: &mut fmt::Formatter<'_>, attributes: &Attributes) -> fmt::Result {
+    for (name, value) in attributes.iter() {
+        write!(f, " {}=\"{}\"", name, one_line(value).replace('"', "'"))?;
+    }
+    Ok(())
+}
+
+/// `text` with line breaks replaced by spaces, so it cannot start a new
+/// directive or entry
+fn one_line(text: &str) -> Cow<'_, str> {
+    if text.contains(['\r', '\n']) {
+        Cow::Owned(text.replace(['\r', '\n'], " "))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// `key="value"` pairs in the order they appeared. Names are matched
+/// ignoring case.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attributes(Vec<(String, String)>);
+
+impl Attributes {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Set `name`, replacing an earlier value
+    pub fn set(&mut self, name: &str, value: impl Into<String>) {
+        let value = value.into();
+        match self.0.iter_mut().find(|(key, _)| key.eq_ignore_ascii_case(name)) {
+            Some((_, existing)) => *existing = value,
+            None => self.0.push((name.to_string(), value)),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Something in the playlist that was not as expected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Directives collected for the next URI
+#[derive(Default)]
+struct Pending {
+    extinf: Option<Extinf>,
+    group: Option<String>,
+    vlc_options: Attributes,
+    kodi_props: Attributes,
+    other_tags: Vec<String>,
+}
+
+/// Parse M3U text. Accepts `\n`, `\r\n` and `\r` line endings and a
+/// leading byte order mark.
+pub fn parse(content: &str) -> Document {
+    let mut document = Document::default();
+    let mut pending = Pending::default();
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content)
+        .replace("\r\n", "\n")
+        .replace('\r', "\n");
+
+    for (index, line) in content.split('\n').enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let number = index + 1;
+        let line = line.trim();
+
+        if let Some(rest) = line.strip_prefix("#EXTM3U") {
+            if document.header.is_some() || !document.entries.is_empty() || pending.extinf.is_some() {
+                document.warn(number, "#EXTM3U header repeated or not at the top; ignored");
+            } else {
+                document.header = Some(Header {
+                    line: number,
+                    attributes: parse_attributes(rest, number, &mut document.warnings).0,
+                });
+            }
+        } else if let Some(rest) = line.strip_prefix("#EXTINF:") {
+            if let Some(orphan) = pending.extinf.take() {
+                document.warn(orphan.line, "#EXTINF without a URI; dropped");
+            }
+            pending.extinf = Some(parse_extinf(rest, number, &mut document.warnings));
+        } else if let Some(group) = line.strip_prefix("#EXTGRP:") {
+            pending.group = Some(group.trim().to_string());
+        } else if let Some(option) = line.strip_prefix("#EXTVLCOPT:") {
+            set_option(&mut pending.vlc_options, option, number, &mut document.warnings);
+        } else if let Some(prop) = line.strip_prefix("#KODIPROP:") {
+            set_option(&mut pending.kodi_props, prop, number, &mut document.warnings);
+        } else if line.starts_with('#') {
+            // Comments and directives this parser does not model
+            if line.starts_with("#EXT") {
+                pending.other_tags.push(line.to_string());
+            }
+        } else {
+            let pending = std::mem::take(&mut pending);
+            if pending.extinf.is_none() && document.header.is_some() {
+                document.warn(number, "URI without #EXTINF");
+            }
+            document.entries.push(Entry {
+                line: number,
+                extinf: pending.extinf,
+                group: pending.group,
+                vlc_options: pending.vlc_options,
+                kodi_props: pending.kodi_props,
+                other_tags: pending.other_tags,
+                uri: line.to_string(),
+            });
+        }
+    }
+
+    if let Some(orphan) = pending.extinf {
+        document.warn(orphan.line, "#EXTINF at the end of the playlist without a URI; dropped");
+    }
+    if document.header.is_none() {
+        document.warn(1, "missing #EXTM3U header");
+    }
+
+    document
+}
+
+/// Read and parse an M3U file, in whatever charset it was written
+pub fn parse_file(path: &Path) -> io::Result<Document> {
+    Ok(parse(&decode(&fs::read(path)?, None).0))
+}
+
+/// Text of a playlist and the charset it was written in.
+///
+/// A byte order mark wins, then the charset of `content_type`, unless it
+/// claims UTF-8 for bytes that are not. Otherwise the text is UTF-8 if it
+/// decodes as such, or else the charset that fits the bytes best, such as
+/// Windows-1251 or Latin-1.
+pub fn decode(bytes: &[u8], content_type: Option<&str>) -> (String, &'static Encoding) {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_length..]);
+        return (text.into_owned(), encoding);
+    }
+
+    let is_utf8 = std::str::from_utf8(bytes).is_ok();
+    let declared = content_type
+        .and_then(declared_charset)
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .filter(|&encoding| encoding != UTF_8 || is_utf8);
+
+    let encoding = match declared {
+        Some(encoding) => encoding,
+        None if is_utf8 => UTF_8,
+        None => {
+            let mut detector = EncodingDetector::new();
+            detector.feed(bytes, true);
+            detector.guess(None, false)
+        }
+    };
+
+    let (text, _) = encoding.decode_without_bom_handling(bytes);
+    (text.into_owned(), encoding)
+}
+
+/// `charset` parameter of a `Content-Type` value
+fn declared_charset(content_type: &str) -> Option<&str> {
+    content_type.split(';')
+        .skip(1)
+        .filter_map(|parameter| parameter.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .map(|(_, value)| value.trim().trim_matches('"'))
+        .filter(|value| !value.is_empty())
+}
+
+/// A title fit for display: control characters dropped and runs of
+/// whitespace, including non-breaking spaces, turned into one space
+pub fn sanitize_title(title: &str) -> String {
+    title.chars()
+        .filter(|c| !c.is_control() || c.is_whitespace())
+        .filter(|&c| c != '\u{feff}')
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The playlist as M3U text, with an `#EXTM3U` header whether or not the
+/// document had one
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.header {
+            Some(header) => writeln!(f, "{}", header)?,
+            None => writeln!(f, "#EXTM3U")?,
+        }
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+impl Document {
+    /// URIs of all entries, in order
+    pub fn uris(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|entry| entry.uri.as_str())
+    }
+
+    fn warn(&mut self, line: usize, message: &str) {
+        self.warnings.push(Warning {
+            line,
+            message: message.to_string(),
+        });
+    }
+}
+
+/// `duration attributes,title` after `#EXTINF:`
+fn parse_extinf(rest: &str, line: usize, warnings: &mut Vec<Warning>) -> Extinf {
+    let duration_end = rest.find(|c: char| c.is_whitespace() || c == ',').unwrap_or(rest.len());
+    let duration = match rest[..duration_end].parse::<f64>() {
+        Ok(duration) => duration,
+        Err(_) => {
+            warnings.push(Warning {
+                line,
+                message: format!("invalid #EXTINF duration '{}', using -1", &rest[..duration_end]),
+            });
+            -1.0
+        }
+    };
+
+    let (attributes, consumed) = parse_attributes(&rest[duration_end..], line, warnings);
+    let after = &rest[duration_end + consumed..];
+    let title = match after.strip_prefix(',') {
+        Some(title) => sanitize_title(&unquote(title.trim())),
+        None => {
+            warnings.push(Warning {
+                line,
+                message: "#EXTINF without a title".to_string(),
+            });
+            String::new()
+        }
+    };
+
+    Extinf {
+        line,
+        duration,
+        attributes,
+        title,
+    }
+}
+
+/// `key="value" key=value ...` up to the first comma outside quotes.
+///
+/// Returns the attributes and the number of bytes consumed.
+fn parse_attributes(text: &str, line: usize, warnings: &mut Vec<Warning>) -> (Attributes, usize) {
+    let mut attributes = Attributes::default();
+    let bytes = text.as_bytes();
+    let mut pos = 0;
+
+    loop {
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if pos >= bytes.len() || bytes[pos] == b',' {
+            return (attributes, pos);
+        }
+
+        let name_start = pos;
+        while pos < bytes.len() && !matches!(bytes[pos], b'=' | b',') && !bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        let name = &text[name_start..pos];
+
+        if pos >= bytes.len() || bytes[pos] != b'=' {
+            warnings.push(Warning {
+                line,
+                message: format!("attribute '{}' without a value", name),
+            });
+            continue;
+        }
+        pos += 1;
+
+        let value = if bytes.get(pos) == Some(&b'"') {
+            match text[pos + 1..].find('"') {
+                Some(end) => {
+                    let value = &text[pos + 1..pos + 1 + end];
+                    pos += end + 2;
+                    value
+                }
+                None => {
+                    warnings.push(Warning {
+                        line,
+                        message: format!("unterminated quote in attribute '{}'", name),
+                    });
+                    let value = &text[pos + 1..];
+                    pos = bytes.len();
+                    value
+                }
+            }
+        } else {
+            let value_start = pos;
+            while pos < bytes.len() && bytes[pos] != b',' && !bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            &text[value_start..pos]
+        };
+
+        attributes.set(name, value);
+    }
+}
+
+/// `key=value` of an `#EXTVLCOPT` or `#KODIPROP` line
+fn set_option(options: &mut Attributes, option: &str, line: usize, warnings: &mut Vec<Warning>) {
+    match option.split_once('=') {
+        Some((key, value)) => options.set(key.trim(), value.trim()),
+        None => warnings.push(Warning {
+            line,
+            message: format!("option '{}' without a value", option.trim()),
+        }),
+    }
+}
+
+/// Title without the quotes some generators put around it
+fn unquote(title: &str) -> String {
+    match title.strip_prefix('"').and_then(|title| title.strip_suffix('"')) {
+        Some(inner) => inner.to_string(),
+        None => title.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(document: &Document) -> Vec<(usize, &str)> {
+        document.warnings.iter().map(|warning| (warning.line, warning.message.as_str())).collect()
+    }
+
+    #[test]
+    fn quoted_commas_in_attributes_and_titles() {
+        let document = parse("#EXTM3U\n#EXTINF:-1 tvg-name=\"News, 24h\" group-title=\"A,B\",Channel, with \"comma\"\nhttp://host/1\n");
+
+        let entry = &document.entries[0];
+        assert_eq!(entry.tvg_name(), Some("News, 24h"));
+        assert_eq!(entry.group(), Some("A,B"));
+        assert_eq!(entry.title(), "Channel, with \"comma\"");
+        assert!(document.warnings.is_empty());
+    }
+
+    #[test]
+    fn quoted_title_is_unquoted() {
+        let document = parse("#EXTM3U\n#EXTINF:-1,\"Quoted, title\"\nhttp://host/1\n");
+        assert_eq!(document.entries[0].title(), "Quoted, title");
+    }
+
+    #[test]
+    fn byte_order_mark_and_line_endings() {
+        let crlf = parse("\u{feff}#EXTM3U\r\n#EXTINF:-1,One\r\nhttp://host/1\r\n");
+        let cr = parse("#EXTM3U\r#EXTINF:-1,One\rhttp://host/1\r");
+
+        for document in [&crlf, &cr] {
+            assert!(document.header.is_some());
+            assert_eq!(document.entries.len(), 1);
+            assert_eq!(document.entries[0].title(), "One");
+            assert_eq!(document.entries[0].uri, "http://host/1");
+            assert_eq!(document.entries[0].line, 3);
+            assert!(document.warnings.is_empty());
+        }
+    }
+
+    #[test]
+    fn unquoted_and_empty_attributes() {
+        let document = parse("#EXTM3U\n#EXTINF:-1 tvg-id=one.tv tvg-logo=\"\" catchup=default,One\nhttp://host/1\n");
+
+        let entry = &document.entries[0];
+        assert_eq!(entry.tvg_id(), Some("one.tv"));
+        assert_eq!(entry.tvg_logo(), Some(""));
+        assert_eq!(entry.catchup(), Some("default"));
+        assert_eq!(entry.attribute("TVG-ID"), Some("one.tv"));
+        assert_eq!(entry.title(), "One");
+    }
+
+    #[test]
+    fn group_and_option_directives() {
+        let document = parse(concat!(
+            "#EXTM3U\n",
+            "#EXTINF:-1,One\n",
+            "#EXTGRP:Sports\n",
+            "#EXTVLCOPT:http-user-agent=Mozilla/5.0\n",
+            "#KODIPROP:inputstream.adaptive.license_type=clearkey\n",
+            "#EXT-X-CUSTOM:1\n",
+            "# plain comment\n",
+            "http://host/1\n",
+            "#EXTINF:-1 group-title=\"Movies\",Two\n",
+            "#EXTGRP:Ignored\n",
+            "http://host/2\n",
+        ));
+
+        let one = &document.entries[0];
+        assert_eq!(one.group(), Some("Sports"));
+        assert_eq!(one.vlc_options.get("http-user-agent"), Some("Mozilla/5.0"));
+        assert_eq!(one.kodi_props.get("inputstream.adaptive.license_type"), Some("clearkey"));
+        assert_eq!(one.other_tags, vec!["#EXT-X-CUSTOM:1".to_string()]);
+
+        // group-title wins over #EXTGRP
+        assert_eq!(document.entries[1].group(), Some("Movies"));
+        assert!(document.entries[1].other_tags.is_empty());
+    }
+
+    #[test]
+    fn warnings_carry_line_numbers() {
+        let document = parse(concat!(
+            "#EXTINF:abc,One\n",
+            "http://host/1\n",
+            "\n",
+            "#EXTINF:-1 tvg-id,Two\n",
+            "#EXTINF:-1 tvg-name=\"open,Three\n",
+            "http://host/3\n",
+            "#EXTVLCOPT:no-value\n",
+            "#EXTM3U\n",
+            "#EXTINF:-1\n",
+        ));
+
+        assert_eq!(messages(&document), vec![
+            (1, "invalid #EXTINF duration 'abc', using -1"),
+            (4, "attribute 'tvg-id' without a value"),
+            (4, "#EXTINF without a URI; dropped"),
+            (5, "unterminated quote in attribute 'tvg-name'"),
+            (5, "#EXTINF without a title"),
+            (7, "option 'no-value' without a value"),
+            (8, "#EXTM3U header repeated or not at the top; ignored"),
+            (9, "#EXTINF without a title"),
+            (9, "#EXTINF at the end of the playlist without a URI; dropped"),
+            (1, "missing #EXTM3U header"),
+        ]);
+        assert_eq!(document.entries.len(), 2);
+        assert_eq!(document.entries[1].line, 6);
+    }
+
+    #[test]
+    fn uri_without_extinf() {
+        let document = parse("#EXTM3U\nhttp://host/bare\n");
+
+        assert_eq!(document.entries[0].title(), "http://host/bare");
+        assert_eq!(messages(&document), vec![(2, "URI without #EXTINF")]);
+    }
+
+    #[test]
+    fn round_trip_through_the_writer() {
+        let text = concat!(
+            "#EXTM3U x-tvg-url=\"http://guide/epg.xml\"\n",
+            "#EXTINF:-1 tvg-id=\"one.tv\" tvg-name=\"One, HD\" group-title=\"News\",One, HD\n",
+            "#EXTGRP:Extra\n",
+            "#EXTVLCOPT:http-referrer=http://host/\n",
+            "#KODIPROP:inputstream=inputstream.adaptive\n",
+            "#EXT-X-CUSTOM:1\n",
+            "http://host/1\n",
+            "#EXTINF:10.5,Two\n",
+            "rtp://239.0.0.1:1234\n",
+        );
+
+        let document = parse(text);
+        let written = document.to_string();
+        assert_eq!(written, text);
+
+        let reparsed = parse(&written);
+        assert_eq!(reparsed.entries.len(), document.entries.len());
+        for (before, after) in document.entries.iter().zip(&reparsed.entries) {
+            assert_eq!(before.title(), after.title());
+            assert_eq!(before.uri, after.uri);
+            assert_eq!(before.extinf.as_ref().map(|extinf| &extinf.attributes), after.extinf.as_ref().map(|extinf| &extinf.attributes));
+            assert_eq!(before.vlc_options, after.vlc_options);
+            assert_eq!(before.kodi_props, after.kodi_props);
+            assert_eq!(before.other_tags, after.other_tags);
+        }
+    }
+
+    #[test]
+    fn writer_keeps_values_on_one_line() {
+        let mut extinf = Extinf::untitled("Line\nbreak");
+        extinf.attributes.set("tvg-name", "say \"hi\"\r\n");
+        let entry = Entry {
+            line: 1,
+            extinf: Some(extinf),
+            group: None,
+            vlc_options: Attributes::default(),
+            kodi_props: Attributes::default(),
+            other_tags: Vec::new(),
+            uri: "http://host/1".to_string(),
+        };
+
+        assert_eq!(entry.to_string(), "#EXTINF:-1 tvg-name=\"say 'hi'  \",Line break\nhttp://host/1");
+    }
+
+    const CP1251: &[u8] = b"#EXTM3U\n#EXTINF:-1,\xcf\xe5\xf0\xe2\xfb\xe9 \xea\xe0\xed\xe0\xeb\nhttp://h/1\n#EXTINF:-1,\xd0\xee\xf1\xf1\xe8\xff 24\nhttp://h/2\n";
+    const CP1251_TEXT: &str = "#EXTM3U\n#EXTINF:-1,Первый канал\nhttp://h/1\n#EXTINF:-1,Россия 24\nhttp://h/2\n";
+
+    const LATIN1: &[u8] = b"#EXTM3U\n#EXTINF:-1,T\xe9l\xe9 Qu\xe9bec\nhttp://h/1\n#EXTINF:-1,Canal N\xfamero Uno Espa\xf1a\nhttp://h/2\n";
+    const LATIN1_TEXT: &str = "#EXTM3U\n#EXTINF:-1,Télé Québec\nhttp://h/1\n#EXTINF:-1,Canal Número Uno España\nhttp://h/2\n";
+
+    #[test]
+    fn detects_cp1251_and_latin1() {
+        let (text, encoding) = decode(CP1251, Some("audio/x-mpegurl"));
+        assert_eq!(encoding.name(), "windows-1251");
+        assert_eq!(text, CP1251_TEXT);
+
+        // Latin-1 is read as its superset, as browsers do
+        let (text, encoding) = decode(LATIN1, None);
+        assert_eq!(encoding.name(), "windows-1252");
+        assert_eq!(text, LATIN1_TEXT);
+    }
+
+    #[test]
+    fn declared_charset_is_used_unless_falsely_utf8() {
+        let (text, encoding) = decode(CP1251, Some("application/x-mpegurl; charset=\"cp1251\""));
+        assert_eq!(encoding.name(), "windows-1251");
+        assert_eq!(text, CP1251_TEXT);
+
+        let (text, encoding) = decode(CP1251, Some("application/x-mpegurl; charset=utf-8"));
+        assert_eq!(encoding.name(), "windows-1251");
+        assert_eq!(text, CP1251_TEXT);
+
+        let (text, encoding) = decode(CP1251_TEXT.as_bytes(), Some("application/x-mpegurl; charset=UTF-8"));
+        assert_eq!(encoding, UTF_8);
+        assert_eq!(text, CP1251_TEXT);
+    }
+
+    #[test]
+    fn byte_order_mark_overrides_declared_charset() {
+        let mut utf8 = b"\xef\xbb\xbf".to_vec();
+        utf8.extend_from_slice(CP1251_TEXT.as_bytes());
+        let (text, encoding) = decode(&utf8, Some("audio/x-mpegurl; charset=windows-1251"));
+        assert_eq!(encoding, UTF_8);
+        assert_eq!(text, CP1251_TEXT);
+
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend(CP1251_TEXT.encode_utf16().flat_map(u16::to_le_bytes));
+        let (text, encoding) = decode(&utf16, Some("audio/x-mpegurl; charset=iso-8859-1"));
+        assert_eq!(encoding.name(), "UTF-16LE");
+        assert_eq!(text, CP1251_TEXT);
+    }
+
+    #[test]
+    fn plain_ascii_and_utf8_stay_utf8() {
+        assert_eq!(decode(b"#EXTM3U\nhttp://h/1\n", None).1, UTF_8);
+        assert_eq!(decode(LATIN1_TEXT.as_bytes(), None), (LATIN1_TEXT.to_string(), UTF_8));
+    }
+
+    #[test]
+    fn titles_are_sanitized() {
+        assert_eq!(sanitize_title("  One \t HD  "), "One HD");
+        assert_eq!(sanitize_title("One\u{0}\u{7}\u{1b}Two"), "OneTwo");
+        assert_eq!(sanitize_title("One\u{a0}\u{85}Two\u{feff}"), "One Two");
+        assert_eq!(sanitize_title("\r\n"), "");
+
+        let document = parse("#EXTM3U\n#EXTINF:-1 tvg-id=\"x\",  \u{1}News \u{7f} 24\t\nhttp://h/1\n");
+        assert_eq!(document.entries[0].title(), "News 24");
+    }
+
+}
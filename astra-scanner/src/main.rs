@@ -1,15 +1,33 @@
 use std::env;
 use std::error::Error;
+use std::net::SocketAddr;
+use std::path::Path;
+
+use config::Config;
+use lang::LanguageManager;
+use scanner::AstraScanner;
 
 pub mod asn;
+pub mod catalog;
+pub mod channel;
 pub mod config;
+pub mod detect;
+pub mod epg;
+pub mod export;
+pub mod fingerprint;
 pub mod gui;
+pub mod http;
 pub mod lang;
+pub mod m3u;
+pub mod playlist;
+pub mod refingerprint;
 pub mod scanner;
+pub mod snapshot;
+pub mod store;
+pub mod tls;
 pub mod ui;
-pub mod channel {
-    // Empty module to fix imports
-}
+pub mod utils;
+pub mod verify;
 
 fn main() -> Result<(), Box<dyn Error>> {
     // Parse command line arguments
@@ -19,14 +37,27 @@ fn main() -> Result<(), Box<dyn Error>> {
     if args.len() > 1 {
         match args[1].as_str() {
             "gui" => run_gui()?,
+            "scan" => run_scan(&args[2..])?,
+            "refingerprint" => run_refingerprint()?,
+            "exposure" => run_exposure_report()?,
+            "playlist" => run_playlist(&args[2..])?,
+            "epg" => run_epg_coverage(&args[2..])?,
+            "verify" => run_verify(&args[2..])?,
             _ => {
                 println!("Unknown command: {}", args[1]);
                 println!("Available commands:");
                 println!("  gui - Launch the graphical interface");
+                println!("  scan [--source <ip|interface>] - Scan pool/ip.txt and pool/ports.txt");
+                println!("  refingerprint - Match saved probe responses against the current detectors");
+                println!("  exposure - Report which servers publish their playlist openly");
+                println!("  playlist snapshots <ip:port> - List the stored versions of a server's playlist");
+                println!("  playlist diff <ip:port> [from] [to] - Compare two versions (default: the latest two)");
+                println!("  epg [guide.xml] - Report how many recorded channels the XMLTV guide covers");
+                println!("  verify <file.m3u> - Check every entry of a playlist and write an annotated copy and a CSV report");
             }
         }
     } else {
-        println!("Astra Scanner - A tool for discovering Astra servers");
+        println!("Astra Scanner - A tool for discovering streaming servers");
         println!("Run with 'gui' to launch the graphical interface");
     }
     
@@ -38,4 +69,149 @@ fn run_gui() -> Result<(), Box<dyn Error>> {
     // Just run the GUI directly
     gui::run()?;
     Ok(())
-} 
\ No newline at end of file
+}
+
+/// Runs a command-line scan
+fn run_scan(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut config = Config::load().unwrap_or_default();
+    
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--source" => {
+                let source = args.next().ok_or("--source requires an IP address or interface name")?;
+                config.scanner.source_address = Some(source.clone());
+            }
+            other => return Err(format!("Unknown option: {}", other).into()),
+        }
+    }
+    
+    let lang = LanguageManager::new(&config.language.default);
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let mut scanner = AstraScanner::new(&lang, &config)?;
+        scanner.scan().await
+    })?;
+    
+    Ok(())
+}
+
+/// Re-runs detection on the responses saved by earlier scans
+fn run_refingerprint() -> Result<(), Box<dyn Error>> {
+    let config = Config::load().unwrap_or_default();
+    let summary = refingerprint::run(&config)?;
+    
+    println!("Replayed saved responses from {} targets", summary.targets);
+    println!("  {} servers updated", summary.updated);
+    println!("  {} servers no longer matched, marked unconfirmed", summary.unmatched);
+    println!("  {} new servers found", summary.added);
+    
+    Ok(())
+}
+
+/// Writes the playlist exposure report for all recorded servers
+fn run_exposure_report() -> Result<(), Box<dyn Error>> {
+    let servers = store::load_server_records();
+    let (path, summary) = export::export_exposure(&servers)?;
+    
+    println!("Playlist exposure of {} servers", servers.len());
+    println!("  {} open to anyone", summary.open);
+    println!("  {} protected", summary.protected);
+    println!("  {} without a playlist", summary.unavailable);
+    println!("  {} not checked", summary.unchecked);
+    println!("Report written to {}", path.display());
+    
+    Ok(())
+}
+
+/// Lists or compares the stored snapshots of a server's playlist
+fn run_playlist(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let usage = "Usage: playlist snapshots <ip:port> | playlist diff <ip:port> [from] [to]";
+    let (command, server) = match args {
+        [command, server, ..] => (command.as_str(), server.parse::<SocketAddr>().map_err(|_| format!("Invalid server address: {}", server))?),
+        _ => return Err(usage.into()),
+    };
+    
+    match command {
+        "snapshots" => {
+            let snapshots = snapshot::list(server);
+            println!("{} snapshots of {}", snapshots.len(), server);
+            for snapshot in &snapshots {
+                println!("  {}  {} entries from {} ({})", snapshot.id(), snapshot.entries, snapshot.path, snapshot.encoding.as_deref().unwrap_or("charset unknown"));
+            }
+        }
+        "diff" => {
+            let (from, to) = snapshot::select(server, args.get(2).map(String::as_str), args.get(3).map(String::as_str))?;
+            let diff = snapshot::diff(&from.channels()?, &to.channels()?);
+            
+            println!("Playlist of {}: {} -> {}", server, from.id(), to.id());
+            for channel in &diff.added {
+                println!("  + {} [{}] {}", channel.name, channel.group.as_deref().unwrap_or("-"), channel.url);
+            }
+            for channel in &diff.removed {
+                println!("  - {} [{}] {}", channel.name, channel.group.as_deref().unwrap_or("-"), channel.url);
+            }
+            for (before, after) in &diff.renamed {
+                println!("  ~ {} -> {} {}", before.name, after.name, after.url);
+            }
+            for (before, after) in &diff.moved {
+                println!("  > {}: [{}] -> [{}]", after.name, before.group.as_deref().unwrap_or("-"), after.group.as_deref().unwrap_or("-"));
+            }
+            println!("{} added, {} removed, {} renamed, {} moved", diff.added.len(), diff.removed.len(), diff.renamed.len(), diff.moved.len());
+        }
+        _ => return Err(usage.into()),
+    }
+    
+    Ok(())
+}
+
+/// Reports the guide coverage of the recorded channels
+fn run_epg_coverage(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let config = Config::load().unwrap_or_default();
+    let path = args.first().cloned()
+        .or(config.epg.file)
+        .ok_or("No guide given and epg.file is not set in pool/config.json")?;
+    
+    let guide = epg::Guide::load(Path::new(&path))?;
+    let groups = catalog::group(store::load_channel_records());
+    let coverage = epg::coverage(&guide, &groups);
+    
+    println!("Guide {}: {} channels, {} programmes", path, guide.channels.len(), guide.programme_count());
+    println!("{} of {} channels covered ({:.1}%)", coverage.matched(), coverage.channels, coverage.percent());
+    println!("  {} by tvg-id", coverage.by_tvg_id);
+    println!("  {} by name", coverage.by_name);
+    
+    let unmatched: Vec<&str> = groups.iter()
+        .filter(|group| group.sources.iter().all(|source| guide.find(source).is_none()))
+        .map(|group| group.name.as_str())
+        .collect();
+    if !unmatched.is_empty() {
+        println!("Without guide data:");
+        for name in unmatched.iter().take(20) {
+            println!("  {}", name);
+        }
+        if unmatched.len() > 20 {
+            println!("  ... and {} more", unmatched.len() - 20);
+        }
+    }
+    
+    Ok(())
+}
+
+/// Checks the entries of a local playlist file
+fn run_verify(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let path = args.first().ok_or("Usage: verify <file.m3u>")?;
+    let config = Config::load().unwrap_or_default();
+    
+    let runtime = tokio::runtime::Runtime::new()?;
+    let verification = runtime.block_on(verify::verify_file(Path::new(path), &config))?;
+    
+    println!("{}: {} entries", path, verification.entries);
+    println!("  {} working", verification.working);
+    println!("  {} failed", verification.failed);
+    println!("  {} not verifiable by their scheme", verification.unverified);
+    println!("Annotated playlist: {}", verification.playlist.display());
+    println!("Report: {}", verification.report.display());
+    
+    Ok(())
+}
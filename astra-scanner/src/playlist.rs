@@ -0,0 +1,380 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::time::Duration;
+use anyhow::{anyhow, bail, Result};
+use encoding_rs::Encoding;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::header::{HeaderMap, CONTENT_TYPE, WWW_AUTHENTICATE};
+use reqwest::{Client, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::channel::{self, Channel};
+use crate::config::ScannerConfig;
+use crate::http;
+use crate::m3u::{self, Document};
+use crate::scanner::Server;
+
+/// Content types servers use for M3U playlists
+const M3U_CONTENT_TYPES: [&str; 5] = [
+    "application/vnd.apple.mpegurl",
+    "application/x-mpegurl",
+    "application/mpegurl",
+    "audio/x-mpegurl",
+    "audio/mpegurl",
+];
+
+lazy_static! {
+    static ref REALM: Regex = Regex::new(r#"(?i)\brealm\s*=\s*(?:"([^"]*)"|([^,\s]+))"#).unwrap();
+}
+
+/// How a server answers for its playlist
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum PlaylistAccess {
+    /// Served to anyone, without credentials
+    Open,
+    /// Refused without credentials
+    Protected(Protection),
+    /// No candidate path served a playlist
+    Unavailable,
+}
+
+impl PlaylistAccess {
+    /// Access of a server given the outcome of `fetch`
+    pub fn of(result: &Result<Playlist>) -> Self {
+        match result {
+            Ok(_) => Self::Open,
+            Err(e) => match e.downcast_ref::<Protection>() {
+                Some(protection) => Self::Protected(protection.clone()),
+                None => Self::Unavailable,
+            },
+        }
+    }
+
+    /// Short name for reports
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Protected(_) => "protected",
+            Self::Unavailable => "unavailable",
+        }
+    }
+}
+
+/// A playlist endpoint that answered 401 or 403.
+///
+/// The scanner records it and moves on; it never asks again with
+/// credentials or tokens of any kind. A detector that meets it while
+/// confirming a server carries it in its `Confirmation`, so the playlist
+/// fetch does not request that path a second time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Protection {
+    pub path: String,
+    pub status: u16,
+    /// Scheme of the `WWW-Authenticate` challenge, e.g. `Basic` or `Digest`
+    pub scheme: Option<String>,
+    pub realm: Option<String>,
+}
+
+impl Protection {
+    /// Protection of `path` if its response refused access
+    pub fn of_response(path: &str, status: StatusCode, headers: &HeaderMap) -> Option<Self> {
+        if status != StatusCode::UNAUTHORIZED && status != StatusCode::FORBIDDEN {
+            return None;
+        }
+        let challenge = headers.get(WWW_AUTHENTICATE).and_then(|value| value.to_str().ok());
+        Some(Self::new(path, status, challenge))
+    }
+
+    fn new(path: &str, status: StatusCode, challenge: Option<&str>) -> Self {
+        let challenge = challenge.map(str::trim).filter(|challenge| !challenge.is_empty());
+        let scheme = challenge
+            .and_then(|challenge| challenge.split_whitespace().next())
+            .map(|scheme| scheme.trim_end_matches(',').to_string());
+        let realm = challenge
+            .and_then(|challenge| REALM.captures(challenge))
+            .and_then(|captures| captures.get(1).or_else(|| captures.get(2)))
+            .map(|realm| realm.as_str().to_string());
+
+        Self {
+            path: path.to_string(),
+            status: status.as_u16(),
+            scheme,
+            realm,
+        }
+    }
+}
+
+impl fmt::Display for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is protected (status {}", self.path, self.status)?;
+        if let Some(scheme) = &self.scheme {
+            write!(f, ", {}", scheme)?;
+        }
+        if let Some(realm) = &self.realm {
+            write!(f, " realm \"{}\"", realm)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl std::error::Error for Protection {}
+
+/// A channel playlist fetched from a server
+#[derive(Debug, Clone)]
+pub struct Playlist {
+    /// Path that served the playlist, as requested
+    pub path: String,
+    /// URL the playlist came from after redirects; relative channel URLs
+    /// are resolved against it
+    pub url: Url,
+    pub content_type: Option<String>,
+    /// Charset the server sent the playlist in
+    pub encoding: &'static Encoding,
+    /// Text transcoded to UTF-8, without byte order mark
+    pub content: String,
+    /// Parsed content, with warnings for anything malformed
+    pub document: Document,
+}
+
+impl Playlist {
+    /// Channels listed by `server`'s playlist, in order, whatever their URL
+    /// scheme, with relative URLs made absolute.
+    ///
+    /// Entries without an `#EXTINF` line, as in a bare list of URLs, are
+    /// named after their URI.
+    pub fn channels(&self, server: &str) -> Vec<Channel> {
+        self.document.entries.iter()
+            .map(|entry| Channel::from_entry(entry, &resolve(&self.url, &entry.uri), Some(server)))
+            .collect()
+    }
+
+    /// The playlist text with every entry rewritten as an absolute URL, so
+    /// it still works when saved away from the server
+    pub fn with_absolute_urls(&self) -> String {
+        let mut content = String::with_capacity(self.content.len());
+        for line in self.content.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                content.push_str(line);
+            } else {
+                content.push_str(&resolve(&self.url, trimmed));
+            }
+            content.push('\n');
+        }
+        content
+    }
+}
+
+/// Paths to try for `server`'s playlist, in order.
+///
+/// The path that last worked comes first, then the paths configured for the
+/// server's detector in `scanner.playlist_paths`, or else the detector's own.
+pub fn candidate_paths(server: &Server, config: &ScannerConfig) -> Vec<String> {
+    let configured = server.detection.as_ref()
+        .and_then(|detection| config.playlist_paths.get(&detection.detector));
+
+    let mut seen = HashSet::new();
+    server.playlist_path.iter()
+        .chain(configured.unwrap_or(&server.playlist_paths))
+        .filter(|path| seen.insert(path.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Fetch the first of `paths` under `base_url` that serves a playlist.
+///
+/// Every path is requested once, anonymously, and a path `known` to refuse
+/// access, e.g. from the detector's confirmation, is not requested at all.
+/// Redirects are followed by the client. If no path serves a playlist but
+/// one refused access, the error is that path's `Protection`; otherwise it
+/// lists why each path was turned down.
+pub async fn fetch(client: &Client, base_url: &str, paths: &[String], known: Option<&Protection>, config: &ScannerConfig) -> Result<Playlist> {
+    let timeout = Duration::from_secs(config.playlist_timeout as u64);
+
+    let mut protected = None;
+    let mut failures = Vec::new();
+    for path in paths {
+        let result = match known.filter(|protection| protection.path == *path) {
+            Some(protection) => Err(protection.clone().into()),
+            None => fetch_path(client, base_url, path, config.playlist_max_bytes, timeout).await,
+        };
+        match result {
+            Ok(playlist) => return Ok(playlist),
+            Err(e) => {
+                if let Some(protection) = e.downcast_ref::<Protection>() {
+                    protected.get_or_insert_with(|| protection.clone());
+                }
+                failures.push(format!("{}: {}", path, e));
+            }
+        }
+    }
+
+    if let Some(protection) = protected {
+        return Err(protection.into());
+    }
+    if failures.is_empty() {
+        bail!("No playlist paths to try");
+    }
+    Err(anyhow!("No playlist found ({})", failures.join("; ")))
+}
+
+async fn fetch_path(client: &Client, base_url: &str, path: &str, max_bytes: usize, timeout: Duration) -> Result<Playlist> {
+    // Anything else would change the host part of the URL, e.g. `@host`
+    if !path.starts_with('/') {
+        bail!("path must start with /");
+    }
+
+    let response = client.get(format!("{}{}", base_url, path)).timeout(timeout).send().await?;
+    let status = response.status();
+    if let Some(protection) = Protection::of_response(path, status, response.headers()) {
+        return Err(protection.into());
+    }
+    if !status.is_success() {
+        bail!("status {}", status.as_u16());
+    }
+
+    let url = response.url().clone();
+    let content_type = response.headers().get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let sample = http::read_bounded(response, max_bytes, timeout).await?;
+    if sample.truncated {
+        bail!("larger than {} bytes or slower than {}s", max_bytes, timeout.as_secs());
+    }
+
+    let (content, encoding) = m3u::decode(&sample.bytes, content_type.as_deref());
+    sniff(content_type.as_deref(), &content)?;
+
+    Ok(Playlist {
+        path: path.to_string(),
+        url,
+        content_type,
+        encoding,
+        document: m3u::parse(&content),
+        content,
+    })
+}
+
+/// Check that a body is a channel playlist.
+///
+/// A leading `#EXTM3U` is enough. Without it, a playlist content type and at
+/// least one entry are needed. HTML pages and HLS media playlists, which
+/// list the segments of a single stream, are turned down.
+fn sniff(content_type: Option<&str>, content: &str) -> Result<()> {
+    let text = content.trim_start();
+
+    if text.starts_with('<') {
+        bail!("HTML or XML document");
+    }
+    if text.contains("#EXT-X-TARGETDURATION") {
+        bail!("HLS media playlist of a single stream");
+    }
+    if text.starts_with("#EXTM3U") {
+        return Ok(());
+    }
+
+    let m3u_type = content_type
+        .map(|value| value.split(';').next().unwrap_or_default().trim().to_lowercase())
+        .is_some_and(|mime| M3U_CONTENT_TYPES.contains(&mime.as_str()));
+    let has_entries = text.lines().any(|line| {
+        let line = line.trim();
+        !line.is_empty() && !line.starts_with('#')
+    });
+
+    match (m3u_type, has_entries) {
+        (true, true) => Ok(()),
+        (true, false) => bail!("empty playlist"),
+        (false, _) => bail!("no #EXTM3U header (content type {})", content_type.unwrap_or("missing")),
+    }
+}
+
+/// Absolute URL of a playlist entry.
+///
+/// Entries with a scheme are kept as written, so `udp://@239.0.0.1:1234`
+/// keeps its `@`. Relative ones are resolved against the playlist's own URL,
+/// or kept as written if they cannot be.
+pub fn resolve(playlist_url: &Url, entry: &str) -> String {
+    let entry = entry.trim();
+    if channel::url_scheme(entry).is_some() {
+        return entry.to_string();
+    }
+    playlist_url.join(entry)
+        .map(String::from)
+        .unwrap_or_else(|_| entry.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use super::*;
+    use crate::config::Config;
+    use crate::detect::astra::AstraDetector;
+    use crate::detect::{Detection, Detector};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Answer every request with 401 on a local port. Returns the base URL
+    /// and the head of each request received.
+    async fn serve_unauthorized() -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0u8; 4096];
+                let read = socket.read(&mut request).await.unwrap_or(0);
+                received.lock().unwrap().push(String::from_utf8_lossy(&request[..read]).into_owned());
+                let head = "HTTP/1.1 401 Unauthorized\r\n\
+                    WWW-Authenticate: Basic realm=\"IPTV\"\r\n\
+                    Content-Length: 0\r\nConnection: close\r\n\r\n";
+                let _ = socket.write_all(head.as_bytes()).await;
+            }
+        });
+        (format!("http://{}", addr), requests)
+    }
+
+    #[tokio::test]
+    async fn protected_path_is_requested_once_without_credentials() {
+        let (base_url, requests) = serve_unauthorized().await;
+        let config = Config::default();
+        let client = http::client_builder(&config, Duration::from_secs(5)).unwrap().build().unwrap();
+
+        let error = fetch(&client, &base_url, &["/playlist.m3u".to_string()], None, &config.scanner).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<Protection>(), Some(&Protection {
+            path: "/playlist.m3u".to_string(),
+            status: 401,
+            scheme: Some("Basic".to_string()),
+            realm: Some("IPTV".to_string()),
+        }));
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with("GET /playlist.m3u "));
+        assert!(!requests[0].to_lowercase().contains("\nauthorization:"));
+    }
+
+    #[tokio::test]
+    async fn path_refused_during_confirmation_is_not_requested_again() {
+        let (base_url, requests) = serve_unauthorized().await;
+        let config = Config::default();
+        let client = http::client_builder(&config, Duration::from_secs(5)).unwrap().build().unwrap();
+
+        let detection = Detection::new("astra", 60);
+        let confirmation = AstraDetector.confirm(&client, &base_url, &detection, Duration::from_secs(5)).await.unwrap();
+        let known = confirmation.protection.expect("confirmation records the protection");
+
+        let paths = ["/playlist.m3u".to_string()];
+        let error = fetch(&client, &base_url, &paths, Some(&known), &config.scanner).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<Protection>(), Some(&known));
+
+        let requests = requests.lock().unwrap();
+        let playlist_requests = requests.iter()
+            .filter(|request| request.starts_with("GET /playlist.m3u "))
+            .count();
+        assert_eq!(playlist_requests, 1);
+    }
+}
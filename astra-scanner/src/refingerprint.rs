@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::detect::{Detection, DetectorRegistry};
+use crate::scanner::Server;
+use crate::store::{self, FoundServers, RawResponse};
+
+/// Confidence an offline match needs to add a server that no scan recorded.
+/// Confirmation probes are not replayed, so header-only matches count.
+const MIN_OFFLINE_CONFIDENCE: u8 = 50;
+
+/// What a re-fingerprinting pass changed
+#[derive(Debug, Default, Clone)]
+pub struct Summary {
+    /// Targets with saved responses
+    pub targets: usize,
+    /// Recorded servers whose service, version or confidence changed
+    pub updated: usize,
+    /// Recorded servers no detector matches any more, now unconfirmed
+    pub unmatched: usize,
+    /// Servers matched for the first time
+    pub added: usize,
+}
+
+/// Match every saved raw response against the current detectors and rules
+/// and rewrite the server records with the result.
+///
+/// Recorded servers keep their discovery time, web fingerprint and playlist
+/// access. A confirmation from an earlier scan is kept when the same
+/// detector still wins, since it cannot be repeated offline.
+pub fn run(config: &Config) -> Result<Summary> {
+    let registry = DetectorRegistry::load()?;
+    let threshold = config.scanner.confidence_threshold;
+
+    let mut responses: BTreeMap<SocketAddr, Vec<RawResponse>> = BTreeMap::new();
+    for raw in store::load_raw_responses() {
+        responses.entry(raw.addr).or_default().push(raw);
+    }
+
+    let mut summary = Summary {
+        targets: responses.len(),
+        ..Summary::default()
+    };
+
+    let mut servers = store::load_server_records();
+    for server in servers.iter_mut() {
+        let saved = match responses.get(&server.addr()) {
+            Some(saved) => saved,
+            None => continue,
+        };
+
+        match registry.replay(saved, &config.probe) {
+            Some(hit) => {
+                let mut updated = Server::from_hit(server.addr(), hit, threshold);
+                updated.discovery_time = server.discovery_time;
+                updated.web = server.web.take();
+                updated.playlist_path = server.playlist_path.take();
+                updated.playlist_access = server.playlist_access.take();
+                if let (Some(detection), Some(previous)) = (updated.detection.as_mut(), server.detection.as_ref()) {
+                    keep_confirmation(detection, previous);
+                    updated.confirmed = detection.confidence >= threshold;
+                }
+
+                if updated.version_label() != server.version_label() || updated.confidence() != server.confidence() || updated.service != server.service {
+                    summary.updated += 1;
+                }
+                *server = updated;
+            }
+            None => {
+                if server.confirmed {
+                    summary.unmatched += 1;
+                }
+                server.confirmed = false;
+            }
+        }
+    }
+
+    let found_servers = FoundServers::load();
+    for (addr, saved) in &responses {
+        if servers.iter().any(|server| server.addr() == *addr) {
+            continue;
+        }
+
+        let hit = match registry.replay(saved, &config.probe) {
+            Some(hit) if hit.detection.confidence >= MIN_OFFLINE_CONFIDENCE => hit,
+            _ => continue,
+        };
+
+        let mut server = Server::from_hit(*addr, hit, threshold);
+        server.discovery_time = saved[0].captured_at;
+        // Like a scan, only confirmed servers join the found set
+        if server.confirmed {
+            found_servers.record(*addr)?;
+        }
+        servers.push(server);
+        summary.added += 1;
+    }
+
+    store::rewrite_server_records(&servers)?;
+    Ok(summary)
+}
+
+/// Reapply an earlier confirmation to a replayed detection of the same detector
+fn keep_confirmation(detection: &mut Detection, previous: &Detection) {
+    if previous.detector != detection.detector {
+        return;
+    }
+
+    if let Some(confirmation) = &previous.confirmation {
+        detection.confidence = confirmation.confidence;
+        detection.attributes.extend(confirmation.attributes.clone());
+        detection.confirmation = Some(confirmation.clone());
+    }
+}
@@ -1,50 +1,190 @@
-use std::collections::{HashSet};
-use std::fs::{self, File, OpenOptions};
+use std::fs::File;
 use std::io::{BufRead, BufReader, Write};
 use std::path::Path;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use anyhow::{Result, anyhow};
-use reqwest::{Client, header};
+use reqwest::Client;
 use tokio::sync::Mutex;
+use tokio::task::JoinSet;
 use futures::{stream, StreamExt};
 use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 
-use crate::config::Config;
+use crate::config::{Config, ScannerConfig, OPT_OUT_URL};
+use crate::detect::{self, Detection, DetectorRegistry, Hit};
 use crate::lang::LanguageManager;
-use crate::channel;
+use crate::catalog::CatalogWriter;
+use crate::channel::{self, Channel};
+use crate::export;
+use crate::fingerprint::WebFingerprint;
+use crate::http::{self, ResponseMeta};
+use crate::playlist::{self, Playlist, PlaylistAccess, Protection};
+use crate::snapshot;
+use crate::store::{self, FoundServers, RawResponseLog};
+use crate::tls::CertificateInfo;
 use crate::ui::progress::ProgressTracker;
 
-mod internal_channel {
-    pub async fn check_channel(_url: &str) -> bool {
-        // Stub implementation
-        true
-    }
-
-    pub async fn verify_channels(channels: &[(String, String)], _server: &str) -> Vec<(String, String)> {
-        // Stub implementation
-        channels.to_vec()
-    }
-}
-
 /// Astra server scanner
 pub struct AstraScanner<'a> {
     lang: &'a LanguageManager,
     config: &'a Config,
     client: Client,
-    found_servers: Arc<Mutex<HashSet<String>>>,
+    detectors: DetectorRegistry,
+    found_servers: FoundServers,
+    servers: Mutex<Vec<Server>>,
     progress: Option<Arc<Mutex<ProgressTracker>>>,
+    /// Single writer of the output playlists
+    catalog: CatalogWriter,
+    /// Playlist tasks still running, awaited before the scan returns
+    playlist_tasks: std::sync::Mutex<JoinSet<()>>,
 }
 
-/// Representa un servidor Astra encontrado durante el escaneo
-#[derive(Debug, Clone)]
+/// Representa un servidor de streaming encontrado durante el escaneo
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Server {
     pub ip: IpAddr,
     pub port: u16,
     pub service: String,
     pub discovery_time: DateTime<Local>,
+    /// Certificate presented when the server answered over TLS
+    pub tls: Option<CertificateInfo>,
+    /// Detector match that identified the server
+    #[serde(default)]
+    pub detection: Option<Detection>,
+    /// Product name, e.g. `Astra`
+    #[serde(default)]
+    pub product: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub build: Option<String>,
+    /// Edition such as `Pro` or `Lite`
+    #[serde(default)]
+    pub edition: Option<String>,
+    /// Title, headers and hashes of the web interface
+    #[serde(default)]
+    pub web: Option<WebFingerprint>,
+    /// Whether the detection reached the confidence threshold. Playlists of
+    /// unconfirmed servers are not fetched.
+    #[serde(default = "default_confirmed")]
+    pub confirmed: bool,
+    /// Paths where the product serves playlists, empty if it has none
+    #[serde(default = "default_playlist_paths")]
+    pub playlist_paths: Vec<String>,
+    /// Path the playlist was last fetched from
+    #[serde(default)]
+    pub playlist_path: Option<String>,
+    /// Whether the playlist is open or behind a login; `None` until requested
+    #[serde(default)]
+    pub playlist_access: Option<PlaylistAccess>,
+    /// Status line, headers and timings of the probe that matched
+    #[serde(default)]
+    pub response: Option<ResponseMeta>,
+}
+
+// Records saved before confirmation existed were all processed as confirmed
+fn default_confirmed() -> bool {
+    true
+}
+
+// Records saved before other products were detected are all Astra servers
+fn default_playlist_paths() -> Vec<String> {
+    vec!["/playlist.m3u".to_string()]
+}
+
+impl Server {
+    pub fn new(ip: IpAddr, port: u16, service: &str) -> Self {
+        Self {
+            ip,
+            port,
+            service: service.to_string(),
+            discovery_time: Local::now(),
+            tls: None,
+            detection: None,
+            product: None,
+            version: None,
+            build: None,
+            edition: None,
+            web: None,
+            confirmed: true,
+            playlist_paths: Vec::new(),
+            playlist_path: None,
+            playlist_access: None,
+            response: None,
+        }
+    }
+    
+    /// Record for a detector hit, confirmed if it scored at least `threshold`
+    pub fn from_hit(addr: SocketAddr, hit: Hit, threshold: u8) -> Self {
+        let mut server = Self::new(addr.ip(), addr.port(), if hit.certificate.is_some() { "https" } else { "http" });
+        server.tls = hit.certificate;
+        
+        let attribute = |key| hit.detection.attributes.get(key).cloned();
+        server.service = attribute(detect::ATTR_PRODUCT).unwrap_or_else(|| hit.detection.detector.clone());
+        server.product = attribute(detect::ATTR_PRODUCT);
+        server.version = attribute(detect::ATTR_VERSION);
+        server.build = attribute(detect::ATTR_BUILD);
+        server.edition = attribute(detect::ATTR_EDITION);
+        
+        server.confirmed = hit.detection.confidence >= threshold;
+        server.playlist_paths = hit.playlist_paths;
+        server.response = Some(hit.response);
+        server.detection = Some(hit.detection);
+        server
+    }
+    
+    /// Playlist path the detector's confirmation found protected
+    pub fn confirmed_protection(&self) -> Option<&Protection> {
+        self.detection.as_ref()?.confirmation.as_ref()?.protection.as_ref()
+    }
+    
+    /// Detection confidence, 0-100
+    pub fn confidence(&self) -> Option<u8> {
+        self.detection.as_ref().map(|detection| detection.confidence)
+    }
+    
+    /// Product and version for display, e.g. `Astra 5.64 (rc1, Pro)`
+    pub fn version_label(&self) -> String {
+        let mut label = self.product.clone().unwrap_or_else(|| "—".to_string());
+        if let Some(version) = &self.version {
+            label = format!("{} {}", label, version);
+        }
+        
+        let extra: Vec<&str> = [&self.build, &self.edition].into_iter().flatten().map(String::as_str).collect();
+        if !extra.is_empty() {
+            label = format!("{} ({})", label, extra.join(", "));
+        }
+        
+        label
+    }
+    
+    /// Whether the address, version, certificate or web attributes contain
+    /// `query`, ignoring case
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim();
+        if query.is_empty() {
+            return true;
+        }
+        
+        let lower = query.to_lowercase();
+        self.addr().to_string().contains(&lower)
+            || self.version_label().to_lowercase().contains(&lower)
+            || self.tls.as_ref().is_some_and(|cert| cert.sha256.contains(&lower) || cert.subject.to_lowercase().contains(&lower))
+            || self.web.as_ref().is_some_and(|web| web.matches(query))
+    }
+    
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.ip, self.port)
+    }
+    
+    /// URL the server answered on, `https` if it was reached over TLS
+    pub fn base_url(&self) -> String {
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        format!("{}://{}", scheme, self.addr())
+    }
 }
 
 /// Simplified scanner for the GUI
@@ -56,23 +196,28 @@ pub struct SimpleScanner {
 }
 
 impl<'a> AstraScanner<'a> {
-    pub fn new(lang: &'a LanguageManager, config: &'a Config) -> Self {
-        // Create an optimized HTTP client
-        let client = Client::builder()
-            .timeout(Duration::from_millis((config.scanner.connection_timeout * 1000.0) as u64))
+    pub fn new(lang: &'a LanguageManager, config: &'a Config) -> Result<Self> {
+        if config.probe.contact().is_none() {
+            eprintln!("Warning: probe.from is not set, so targets can only reach you through {}", OPT_OUT_URL);
+        }
+        
+        // Create an optimized HTTP client, bound to the configured source address
+        let client = http::client_builder(config, Duration::from_millis((config.scanner.connection_timeout * 1000.0) as u64))?
             .pool_max_idle_per_host(config.scanner.pool_maxsize)
             .tcp_keepalive(Some(Duration::from_secs(15)))
-            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
-            .build()
-            .unwrap_or_default();
+            .build()?;
             
-        Self {
+        Ok(Self {
             lang,
             config,
             client,
-            found_servers: Arc::new(Mutex::new(HashSet::new())),
+            detectors: DetectorRegistry::load()?.with_raw_log(Arc::new(RawResponseLog::open()?)),
+            found_servers: FoundServers::load(),
+            servers: Mutex::new(Vec::new()),
             progress: None,
-        }
+            catalog: CatalogWriter::spawn(config.output.clone())?,
+            playlist_tasks: std::sync::Mutex::new(JoinSet::new()),
+        })
     }
     
     /// Load IPs from file
@@ -118,118 +263,104 @@ impl<'a> AstraScanner<'a> {
         Ok(ports)
     }
     
-    /// Check if a server is an Astra server
+    /// Run the detectors against a server
     async fn check_server(&self, ip: &str, port: u16) -> Result<bool> {
-        // Create server identifier
-        let server = format!("{}:{}", ip, port);
-        
-        // Skip if already found
-        let servers_guard = self.found_servers.lock().await;
-        if servers_guard.contains(&server) {
-            return Ok(false);
-        }
-        drop(servers_guard);
-        
-        // Attempt to connect with HEAD request
-        let url = format!("http://{}:{}", ip, port);
-        let res = self.client.head(&url).send().await;
-        
-        if let Ok(response) = res {
-            // Check for Astra signature in server header
-            if let Some(server_header) = response.headers().get(header::SERVER) {
-                if let Ok(server_value) = server_header.to_str() {
-                    if server_value.contains("Astra") {
-                        // Add to found servers
-                        let mut servers_guard = self.found_servers.lock().await;
-                        if !servers_guard.contains(&server) {
-                            servers_guard.insert(server.clone());
-                            drop(servers_guard);
-                            
-                            // Update progress
-                            if let Some(progress) = &self.progress {
-                                let mut progress_guard = progress.lock().await;
-                                progress_guard.update_servers(1);
-                                drop(progress_guard);
-                            }
-                            
-                            // Save to file
-                            self.save_server(&server).await?;
-                            
-                            // Try to get playlist
-                            self.spawn_playlist_processor(ip.to_string(), port);
-                            
-                            return Ok(true);
-                        }
-                    }
-                }
+        let ip: IpAddr = match ip.trim().parse() {
+            Ok(ip) => ip,
+            Err(_) => return Ok(false),
+        };
+        let server = SocketAddr::new(ip, port);
+        
+        // Probe over TLS first on TLS ports
+        let try_tls = self.config.scanner.tls_ports.contains(&port);
+        let timeout = Duration::from_secs_f64(self.config.scanner.connection_timeout);
+        
+        // Only confirmed hits join the found set, so unconfirmed ones are
+        // probed again by later scans; known servers are skipped
+        if let Some(hit) = self.detectors.run(&self.client, &self.config.probe, server, try_tls, timeout).await {
+            let base_url = hit.base_url.clone();
+            let mut record = Server::from_hit(server, hit, self.config.scanner.confidence_threshold);
+            if record.confirmed && !self.found_servers.record(server)? {
+                return Ok(false);
             }
+            
+            record.web = Some(WebFingerprint::collect(&self.client, &base_url, timeout).await);
+            store::append_server_record(&record)?;
+            self.servers.lock().await.push(record.clone());
+            
+            // Only confirmed servers count as found and get their playlist fetched
+            if !record.confirmed {
+                println!("Unconfirmed server {} (confidence {}), skipping playlist", server, record.confidence().unwrap_or_default());
+                return Ok(false);
+            }
+            
+            // Update progress
+            if let Some(progress) = &self.progress {
+                let mut progress_guard = progress.lock().await;
+                progress_guard.update_servers(1);
+                drop(progress_guard);
+            }
+            
+            if !playlist::candidate_paths(&record, &self.config.scanner).is_empty() {
+                self.spawn_playlist_processor(record);
+            }
+            
+            return Ok(true);
         }
         
         Ok(false)
     }
     
     /// Spawn a task to process a server's playlist
-    fn spawn_playlist_processor(&self, ip: String, port: u16) {
+    fn spawn_playlist_processor(&self, mut record: Server) {
         // Create owned clones of all needed data
         let client = self.client.clone();
-        let playlist_timeout = self.config.scanner.playlist_timeout;
+        let scanner_config = self.config.scanner.clone();
+        let catalog = self.catalog.clone();
         let progress_clone = self.progress.clone();
         
-        // Spawn a self-contained async block
-        tokio::spawn(async move {
+        // Spawn a self-contained async block, kept so the scan can wait for it
+        let mut playlist_tasks = self.playlist_tasks.lock().unwrap();
+        playlist_tasks.spawn(async move {
             // Attempt to get playlist
-            let playlist_result = get_playlist(&client, &ip, port, playlist_timeout).await;
+            let base_url = record.base_url();
+            let playlist_result = fetch_server_playlist(&client, &mut record, &scanner_config).await;
             
-            if let Ok(content) = playlist_result {
-                // Parse channels from playlist
-                let mut channels = Vec::new();
-                let mut current_title = String::new();
-                
-                for line in content.lines() {
-                    if line.starts_with("#EXTINF:") {
-                        current_title = line.to_string();
-                    } else if line.starts_with("http://") || line.starts_with("https://") {
-                        if !current_title.is_empty() {
-                            channels.push((current_title.clone(), line.to_string()));
-                            current_title = String::new();
-                        }
-                    }
+            if let Err(e) = &playlist_result {
+                if let Some(protection) = e.downcast_ref::<Protection>() {
+                    println!("Server {}: {}, not retried", base_url, protection);
                 }
+            }
+            
+            if let Ok(playlist) = playlist_result {
+                report_playlist_warnings(&base_url, &playlist);
+                
+                // Parse channels from playlist
+                let channels = playlist.channels(&base_url);
                 
                 if !channels.is_empty() {
-                    // Create owned versions of channels to avoid lifetime issues
-                    let channels_owned: Vec<(String, String)> = channels;
-                    let server = format!("{}:{}", ip, port);
-                    let channels_count = channels_owned.len();
+                    let server = base_url.clone();
+                    let channels_count = channels.len();
+                    let verifiable_count = channels.iter().filter(|channel| channel.is_verifiable()).count();
                     
-                    // Verify channels
-                    let mut working_channels = Vec::new();
-                    
-                    let results = futures::stream::iter(channels_owned)
-                        .map(|(title, url)| {
-                            let title = title.clone();
-                            let url = url.clone();
-                            async move {
-                                let is_working = internal_channel::check_channel(&url).await;
-                                (title, url, is_working)
-                            }
-                        })
-                        .buffer_unordered(20) // Check 20 channels concurrently
-                        .collect::<Vec<_>>()
-                        .await;
+                    // Verify channels; other schemes are recorded unverified
+                    let results = channel::verify_channels(&client, channels, &scanner_config).await;
                         
-                    for (title, url, is_working) in results {
-                        if is_working {
-                            working_channels.push((title, url));
-                        }
+                    // Record every channel with its check, working or not
+                    if let Err(e) = store::append_channel_records(&results) {
+                        eprintln!("Error saving channel records: {}", e);
                     }
                     
+                    let working_channels: Vec<Channel> = results.into_iter()
+                        .filter(|channel| channel.is_working() == Some(true))
+                        .collect();
+                    
                     println!("Server {}: Verified {}/{} channels, {} working", 
-                             server, channels_count, channels_count, working_channels.len());
+                             server, verifiable_count, channels_count, working_channels.len());
                     
                     if !working_channels.is_empty() {
                         // Save working channels
-                        if let Err(e) = save_working_channels(&working_channels).await {
+                        if let Err(e) = catalog.append(working_channels.clone()).await {
                             eprintln!("Error saving channels: {}", e);
                         }
                         
@@ -244,65 +375,27 @@ impl<'a> AstraScanner<'a> {
         });
     }
     
-    /// Save a found server to file
-    async fn save_server(&self, server: &str) -> Result<()> {
-        let file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open("found_servers.txt")?;
-            
-        let mut file = tokio::fs::File::from_std(file);
-        use tokio::io::AsyncWriteExt;
-        
-        file.write_all(format!("{}\n", server).as_bytes()).await?;
-        file.flush().await?;
-        
-        Ok(())
-    }
-    
     #[allow(dead_code)]
-    async fn get_playlist(&self, ip: &str, port: u16) -> Result<String> {
-        let url = format!("http://{}:{}/playlist.m3u", ip, port);
-        
-        let response = self.client.get(&url)
-            .timeout(Duration::from_secs(self.config.scanner.playlist_timeout as u64))
-            .send()
-            .await?;
-            
-        if response.status().is_success() {
-            let content = response.text().await?;
-            if content.contains("#EXTM3U") {
-                return Ok(content);
-            }
-        }
-        
-        Err(anyhow!("No valid playlist found"))
+    async fn get_playlist(&self, record: &mut Server) -> Result<Playlist> {
+        fetch_server_playlist(&self.client, record, &self.config.scanner).await
     }
     
     #[allow(dead_code)]
-    async fn process_server_playlist(&self, ip: String, port: u16) {
+    async fn process_server_playlist(&self, mut record: Server) {
         // Try to get the playlist
-        let playlist_result = self.get_playlist(&ip, port).await;
+        let base_url = record.base_url();
+        let playlist_result = self.get_playlist(&mut record).await;
         
-        if let Ok(content) = playlist_result {
+        if let Ok(playlist) = playlist_result {
             // Parse channels from playlist
-            let mut channels = Vec::new();
-            let mut current_title = String::new();
-            
-            for line in content.lines() {
-                if line.starts_with("#EXTINF:") {
-                    current_title = line.to_string();
-                } else if line.starts_with("http://") || line.starts_with("https://") {
-                    if !current_title.is_empty() {
-                        channels.push((current_title.clone(), line.to_string()));
-                        current_title = String::new();
-                    }
-                }
-            }
+            let channels = playlist.channels(&base_url);
             
             if !channels.is_empty() {
                 // Verify channels in batches
-                let working_channels = internal_channel::verify_channels(&channels, &format!("{}:{}", ip, port)).await;
+                let working_channels: Vec<Channel> = channel::verify_channels(&self.client, channels, &self.config.scanner).await
+                    .into_iter()
+                    .filter(|channel| channel.is_working() == Some(true))
+                    .collect();
                 
                 if !working_channels.is_empty() {
                     // Save working channels
@@ -321,63 +414,8 @@ impl<'a> AstraScanner<'a> {
     }
     
     #[allow(dead_code)]
-    async fn save_working_channels(&self, channels: &[(String, String)]) -> Result<()> {
-        // Create channels directory if it doesn't exist
-        fs::create_dir_all("channels")?;
-        
-        // Prepare for reading existing channels
-        let path = Path::new("channels/all_channels.m3u8");
-        let mut existing_urls = HashSet::new();
-        
-        // Load existing channels to avoid duplicates
-        if path.exists() {
-            let file = File::open(path)?;
-            let reader = BufReader::new(file);
-            let mut is_url_line = false;
-            
-            for line in reader.lines() {
-                if let Ok(line) = line {
-                    if is_url_line {
-                        existing_urls.insert(line);
-                        is_url_line = false;
-                    } else if line.starts_with("#EXTINF:") {
-                        is_url_line = true;
-                    }
-                }
-            }
-        }
-        
-        // Filter out duplicate channels
-        let new_channels: Vec<_> = channels.iter()
-            .filter(|(_, url)| !existing_urls.contains(url))
-            .collect();
-            
-        if new_channels.is_empty() {
-            return Ok(());
-        }
-        
-        // Append new channels to the file
-        let file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(path)?;
-            
-        let mut writer = std::io::BufWriter::new(file);
-        
-        // Add header if file is new
-        if !path.exists() || fs::metadata(path)?.len() == 0 {
-            writeln!(writer, "#EXTM3U")?;
-        }
-        
-        // Write new channels
-        for (title, url) in new_channels {
-            writeln!(writer, "{}", title)?;
-            writeln!(writer, "{}", url)?;
-        }
-        
-        writer.flush()?;
-        
-        Ok(())
+    async fn save_working_channels(&self, channels: &[Channel]) -> Result<()> {
+        self.catalog.append(channels.to_vec()).await.map(|_| ())
     }
     
     /// Process a chunk of IP:Port combinations
@@ -467,10 +505,18 @@ impl<'a> AstraScanner<'a> {
             }
         }
         
-        // Get final count of found servers
-        let servers_guard = self.found_servers.lock().await;
-        let found_count = servers_guard.len();
-        drop(servers_guard);
+        // Wait for the playlists still being fetched and checked, and for
+        // their channels to be written
+        let mut playlist_tasks = std::mem::take(&mut *self.playlist_tasks.lock().unwrap());
+        while let Some(result) = playlist_tasks.join_next().await {
+            if let Err(e) = result {
+                eprintln!("Playlist task failed: {}", e);
+            }
+        }
+        self.catalog.flush().await?;
+        
+        // Get final count of servers found in this run
+        let found_count = self.found_servers.found_this_run().len();
         
         // Finish progress
         if let Some(progress) = &self.progress {
@@ -491,6 +537,13 @@ impl<'a> AstraScanner<'a> {
         // Save summary
         self.save_summary(total_checked, found_count).await?;
         
+        // Export server records
+        let servers = self.servers.lock().await;
+        if !servers.is_empty() {
+            let path = export::export_servers(&servers)?;
+            println!("Results exported to {}", path.display());
+        }
+        
         Ok(())
     }
     
@@ -498,12 +551,15 @@ impl<'a> AstraScanner<'a> {
     async fn save_summary(&self, total_checked: usize, servers_found: usize) -> Result<()> {
         let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
         
-        let servers_guard = self.found_servers.lock().await;
-        let servers = servers_guard.iter().cloned().collect::<Vec<_>>();
-        drop(servers_guard);
+        let servers = self.found_servers.found_this_run().iter()
+            .map(|addr| addr.to_string())
+            .collect::<Vec<_>>();
+        
+        let source_address = http::source_address(self.config)?.map(|ip| ip.to_string());
         
         let summary = json!({
             "scan_date": timestamp,
+            "source_address": source_address,
             "total_checked": total_checked,
             "servers_found": servers_found,
             "found_servers": servers
@@ -516,83 +572,42 @@ impl<'a> AstraScanner<'a> {
     }
 }
 
-/// Standalone helper function to get playlist content
-async fn get_playlist(client: &Client, ip: &str, port: u16, timeout_secs: usize) -> Result<String> {
-    let url = format!("http://{}:{}/playlist.m3u", ip, port);
+/// Standalone helper function to fetch a server's playlist, recording on the
+/// server record whether it is open or protected and the path that served it
+pub async fn fetch_server_playlist(client: &Client, record: &mut Server, config: &ScannerConfig) -> Result<Playlist> {
+    let paths = playlist::candidate_paths(record, config);
+    let result = playlist::fetch(client, &record.base_url(), &paths, record.confirmed_protection(), config).await;
     
-    let response = client.get(&url)
-        .timeout(Duration::from_secs(timeout_secs as u64))
-        .send()
-        .await?;
-        
-    if response.status().is_success() {
-        let content = response.text().await?;
-        if content.contains("#EXTM3U") {
-            return Ok(content);
+    let access = PlaylistAccess::of(&result);
+    let path = result.as_ref().ok().map(|playlist| playlist.path.clone()).or_else(|| record.playlist_path.clone());
+    if record.playlist_access.as_ref() != Some(&access) || record.playlist_path != path {
+        record.playlist_access = Some(access);
+        record.playlist_path = path;
+        if let Err(e) = store::append_server_record(record) {
+            eprintln!("Error saving playlist access of {}: {}", record.addr(), e);
         }
     }
     
-    Err(anyhow!("No valid playlist found"))
-}
-
-/// Standalone helper function to save working channels
-async fn save_working_channels(channels: &[(String, String)]) -> Result<()> {
-    // Create channels directory if it doesn't exist
-    fs::create_dir_all("channels")?;
-    
-    // Prepare for reading existing channels
-    let path = Path::new("channels/all_channels.m3u8");
-    let mut existing_urls = HashSet::new();
-    
-    // Load existing channels to avoid duplicates
-    if path.exists() {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let mut is_url_line = false;
-        
-        for line in reader.lines() {
-            if let Ok(line) = line {
-                if is_url_line {
-                    existing_urls.insert(line);
-                    is_url_line = false;
-                } else if line.starts_with("#EXTINF:") {
-                    is_url_line = true;
-                }
-            }
+    if let Ok(playlist) = &result {
+        if let Err(e) = snapshot::save(record.addr(), playlist) {
+            eprintln!("Error saving playlist snapshot of {}: {}", record.addr(), e);
         }
     }
     
-    // Filter out duplicate channels
-    let new_channels: Vec<_> = channels.iter()
-        .filter(|(_, url)| !existing_urls.contains(url))
-        .collect();
-        
-    if new_channels.is_empty() {
-        return Ok(());
-    }
-    
-    // Append new channels to the file
-    let file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(path)?;
-        
-    let mut writer = std::io::BufWriter::new(file);
-    
-    // Add header if file is new
-    if !path.exists() || fs::metadata(path)?.len() == 0 {
-        writeln!(writer, "#EXTM3U")?;
+    result
+}
+
+/// Print the first parser warnings of a server's playlist
+pub fn report_playlist_warnings(server: &str, playlist: &Playlist) {
+    let warnings = &playlist.document.warnings;
+    if warnings.is_empty() {
+        return;
     }
     
-    // Write new channels
-    for (title, url) in new_channels {
-        writeln!(writer, "{}", title)?;
-        writeln!(writer, "{}", url)?;
+    println!("Server {}: {} warnings in {}", server, warnings.len(), playlist.url);
+    for warning in warnings.iter().take(5) {
+        println!("  {}", warning);
     }
-    
-    writer.flush()?;
-    
-    Ok(())
 }
 
 impl SimpleScanner {
@@ -613,6 +628,13 @@ impl SimpleScanner {
         self.found_servers.push(server);
     }
     
+    /// Replace the record of a server already added
+    pub fn update_server(&mut self, server: Server) {
+        if let Some(existing) = self.found_servers.iter_mut().find(|existing| existing.addr() == server.addr()) {
+            *existing = server;
+        }
+    }
+    
     pub fn get_servers(&self) -> &Vec<Server> {
         &self.found_servers
     }
@@ -0,0 +1,336 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Local};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::channel::Channel;
+use crate::m3u;
+use crate::playlist::{self, Playlist};
+use crate::store;
+use crate::utils::sha256_hex;
+
+/// Directory holding one subdirectory of playlist snapshots per server
+pub const SNAPSHOTS_DIR: &str = "playlists/snapshots";
+
+/// File holding one JSON `Snapshot` per line
+pub const SNAPSHOT_INDEX_PATH: &str = "playlists/snapshots/index.jsonl";
+
+/// Hex digits of the hash kept in snapshot ids
+const ID_HASH_LEN: usize = 12;
+
+/// File in a server's snapshot directory holding its latest `Snapshot`
+const LATEST_FILE: &str = "latest.json";
+
+/// A playlist as a server published it at one point in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub server: SocketAddr,
+    pub captured_at: DateTime<Local>,
+    /// SHA-256 of the playlist text
+    pub sha256: String,
+    /// Path that served the playlist
+    pub path: String,
+    /// URL the playlist came from, which relative entries resolve against
+    pub url: String,
+    pub content_type: Option<String>,
+    /// Charset the server sent the playlist in; the stored text is UTF-8.
+    /// Unknown for snapshots taken before it was recorded.
+    #[serde(default)]
+    pub encoding: Option<String>,
+    pub entries: usize,
+    /// File holding the playlist text as fetched, in UTF-8
+    pub file: PathBuf,
+}
+
+impl Snapshot {
+    /// Capture time and short hash, e.g. `20240131_120000_3f2a9c01b7de`
+    pub fn id(&self) -> String {
+        format!("{}_{}", self.captured_at.format("%Y%m%d_%H%M%S"), &self.sha256[..ID_HASH_LEN.min(self.sha256.len())])
+    }
+
+    /// The snapshot's channels, with relative URLs resolved
+    pub fn channels(&self) -> Result<Vec<Channel>> {
+        let document = m3u::parse_file(&self.file)
+            .with_context(|| format!("Failed to read snapshot {:?}", self.file))?;
+        let url = Url::parse(&self.url)?;
+
+        Ok(document.entries.iter()
+            .map(|entry| Channel::from_entry(entry, &playlist::resolve(&url, &entry.uri), None))
+            .collect())
+    }
+}
+
+/// Store a fetched playlist as a new snapshot of `server`.
+///
+/// Nothing new is stored when the text is the same as the server's latest
+/// snapshot; that snapshot is returned instead.
+pub fn save(server: SocketAddr, playlist: &Playlist) -> Result<Snapshot> {
+    let sha256 = sha256_hex(playlist.content.as_bytes());
+    if let Some(latest) = latest(server).filter(|latest| latest.sha256 == sha256) {
+        return Ok(latest);
+    }
+
+    let mut snapshot = Snapshot {
+        server,
+        captured_at: Local::now(),
+        sha256,
+        path: playlist.path.clone(),
+        url: playlist.url.to_string(),
+        content_type: playlist.content_type.clone(),
+        encoding: Some(playlist.encoding.name().to_string()),
+        entries: playlist.document.entries.len(),
+        file: PathBuf::new(),
+    };
+
+    let dir = Path::new(SNAPSHOTS_DIR).join(server_dir(server));
+    fs::create_dir_all(&dir)?;
+    snapshot.file = dir.join(format!("{}.m3u", snapshot.id()));
+    fs::write(&snapshot.file, &playlist.content)
+        .with_context(|| format!("Failed to write {:?}", snapshot.file))?;
+
+    store::append_lines(Path::new(SNAPSHOT_INDEX_PATH), &format!("{}\n", serde_json::to_string(&snapshot)?))?;
+
+    // Through a temporary file, so the pointer is never read half written
+    let latest_path = dir.join(LATEST_FILE);
+    let temp_path = dir.join(format!("{}.tmp", LATEST_FILE));
+    fs::write(&temp_path, serde_json::to_string(&snapshot)?)
+        .with_context(|| format!("Failed to write {:?}", temp_path))?;
+    fs::rename(&temp_path, &latest_path)
+        .with_context(|| format!("Failed to replace {:?}", latest_path))?;
+
+    Ok(snapshot)
+}
+
+/// Latest snapshot of `server`, without reading the whole index.
+///
+/// Servers snapshotted before the pointer was kept fall back to the index.
+pub fn latest(server: SocketAddr) -> Option<Snapshot> {
+    let dir = Path::new(SNAPSHOTS_DIR).join(server_dir(server));
+    match fs::read_to_string(dir.join(LATEST_FILE)) {
+        Ok(json) => serde_json::from_str(&json).ok(),
+        Err(_) if dir.is_dir() => list(server).pop(),
+        Err(_) => None,
+    }
+}
+
+/// Snapshots of `server`, oldest first
+pub fn list(server: SocketAddr) -> Vec<Snapshot> {
+    let mut snapshots: Vec<Snapshot> = load_index().into_iter()
+        .filter(|snapshot| snapshot.server == server)
+        .collect();
+    snapshots.sort_by_key(|snapshot| snapshot.captured_at);
+    snapshots
+}
+
+/// Every snapshot in the index, in the order stored.
+///
+/// Lines that no longer parse are skipped.
+pub fn load_index() -> Vec<Snapshot> {
+    let file = match File::open(SNAPSHOT_INDEX_PATH) {
+        Ok(file) => file,
+        Err(_) => return Vec::new(),
+    };
+
+    BufReader::new(file).lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect()
+}
+
+/// Two snapshots of `server` to compare, named by id or a unique prefix of
+/// one.
+///
+/// `to` defaults to the latest snapshot and `from` to the one before `to`.
+pub fn select(server: SocketAddr, from: Option<&str>, to: Option<&str>) -> Result<(Snapshot, Snapshot)> {
+    let snapshots = list(server);
+    if snapshots.is_empty() {
+        bail!("No snapshots of {}", server);
+    }
+
+    let find = |id: &str| -> Result<usize> {
+        let matches: Vec<usize> = (0..snapshots.len())
+            .filter(|&position| snapshots[position].id().starts_with(id))
+            .collect();
+        match matches.as_slice() {
+            [position] => Ok(*position),
+            [] => Err(anyhow!("No snapshot of {} matches '{}'", server, id)),
+            _ => Err(anyhow!("'{}' matches {} snapshots of {}", id, matches.len(), server)),
+        }
+    };
+
+    let to = match to {
+        Some(id) => find(id)?,
+        None => snapshots.len() - 1,
+    };
+    let from = match from {
+        Some(id) => find(id)?,
+        None if to > 0 => to - 1,
+        None => bail!("{} has a single snapshot before {}", server, snapshots[to].id()),
+    };
+
+    Ok((snapshots[from].clone(), snapshots[to].clone()))
+}
+
+/// What changed between two versions of a playlist
+#[derive(Debug, Clone, Default)]
+pub struct PlaylistDiff {
+    pub added: Vec<Channel>,
+    pub removed: Vec<Channel>,
+    /// Same stream under a new name, before and after
+    pub renamed: Vec<(Channel, Channel)>,
+    /// Same stream in a new group, before and after
+    pub moved: Vec<(Channel, Channel)>,
+}
+
+impl PlaylistDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.renamed.is_empty() && self.moved.is_empty()
+    }
+}
+
+/// Compare two versions of a playlist.
+///
+/// Channels are matched by stream URL, so a stream whose URL changed shows
+/// as removed and added. A channel both renamed and moved is listed in both.
+pub fn diff(old: &[Channel], new: &[Channel]) -> PlaylistDiff {
+    // Queues, since a playlist may list the same stream more than once
+    let mut by_url: HashMap<&str, VecDeque<&Channel>> = HashMap::new();
+    for channel in old {
+        by_url.entry(channel.url.as_str()).or_default().push_back(channel);
+    }
+
+    let mut diff = PlaylistDiff::default();
+    for channel in new {
+        let before = match by_url.get_mut(channel.url.as_str()).and_then(VecDeque::pop_front) {
+            Some(before) => before,
+            None => {
+                diff.added.push(channel.clone());
+                continue;
+            }
+        };
+
+        if before.name != channel.name {
+            diff.renamed.push((before.clone(), channel.clone()));
+        }
+        if before.group != channel.group {
+            diff.moved.push((before.clone(), channel.clone()));
+        }
+    }
+
+    // Whatever was not matched is gone, in its original order
+    for channel in old {
+        if let Some(queue) = by_url.get_mut(channel.url.as_str()) {
+            if queue.front().is_some_and(|front| std::ptr::eq(*front, channel)) {
+                queue.pop_front();
+                diff.removed.push(channel.clone());
+            }
+        }
+    }
+
+    diff
+}
+
+/// Snapshot subdirectory of a server, e.g. `192.0.2.1_8000`
+fn server_dir(server: SocketAddr) -> String {
+    format!("{}_{}", server.ip().to_string().replace(':', "-"), server.port())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Channels of a playlist given as `(name, group, url)`
+    fn channels(entries: &[(&str, &str, &str)]) -> Vec<Channel> {
+        let text: String = entries.iter()
+            .map(|(name, group, url)| format!("#EXTINF:-1 group-title=\"{}\",{}\n{}\n", group, name, url))
+            .collect();
+        m3u::parse(&format!("#EXTM3U\n{}", text)).entries.iter()
+            .map(|entry| Channel::from_entry(entry, &entry.uri, None))
+            .collect()
+    }
+
+    fn names(channels: &[Channel]) -> Vec<&str> {
+        channels.iter().map(|channel| channel.name.as_str()).collect()
+    }
+
+    fn changes(pairs: &[(Channel, Channel)]) -> Vec<(&str, &str)> {
+        pairs.iter().map(|(before, after)| (before.name.as_str(), after.name.as_str())).collect()
+    }
+
+    #[test]
+    fn identical_playlists_have_no_changes() {
+        let playlist = channels(&[("One", "News", "http://h/1"), ("Two", "News", "http://h/2")]);
+        assert!(diff(&playlist, &playlist).is_empty());
+    }
+
+    #[test]
+    fn added_and_removed_streams() {
+        let old = channels(&[("One", "News", "http://h/1"), ("Two", "News", "http://h/2"), ("Three", "News", "http://h/3")]);
+        let new = channels(&[("Two", "News", "http://h/2"), ("Four", "News", "http://h/4")]);
+
+        let diff = diff(&old, &new);
+        assert_eq!(names(&diff.added), vec!["Four"]);
+        assert_eq!(names(&diff.removed), vec!["One", "Three"]);
+        assert!(diff.renamed.is_empty());
+        assert!(diff.moved.is_empty());
+    }
+
+    #[test]
+    fn renamed_and_moved_streams() {
+        let old = channels(&[("One", "News", "http://h/1"), ("Two", "News", "http://h/2"), ("Three", "News", "http://h/3")]);
+        let new = channels(&[("One HD", "News", "http://h/1"), ("Two", "Sports", "http://h/2"), ("Tres", "Movies", "http://h/3")]);
+
+        let diff = diff(&old, &new);
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert_eq!(changes(&diff.renamed), vec![("One", "One HD"), ("Three", "Tres")]);
+        assert_eq!(changes(&diff.moved), vec![("Two", "Two"), ("Three", "Tres")]);
+    }
+
+    #[test]
+    fn new_url_is_removed_and_added() {
+        let old = channels(&[("One", "News", "http://h/1")]);
+        let new = channels(&[("One", "News", "http://h/1b")]);
+
+        let diff = diff(&old, &new);
+        assert_eq!(names(&diff.added), vec!["One"]);
+        assert_eq!(names(&diff.removed), vec!["One"]);
+    }
+
+    #[test]
+    fn duplicated_streams_are_matched_in_order() {
+        let old = channels(&[("A", "News", "http://h/dup"), ("B", "News", "http://h/dup"), ("C", "News", "http://h/dup")]);
+
+        // One copy fewer: the last one is gone
+        let fewer = diff(&old, &channels(&[("A", "News", "http://h/dup"), ("B", "News", "http://h/dup")]));
+        assert_eq!(names(&fewer.removed), vec!["C"]);
+        assert!(fewer.added.is_empty());
+        assert!(fewer.renamed.is_empty());
+
+        // One copy more: the extra one is new
+        let more = diff(&old[..1], &channels(&[("A", "News", "http://h/dup"), ("A2", "News", "http://h/dup")]));
+        assert_eq!(names(&more.added), vec!["A2"]);
+        assert!(more.removed.is_empty());
+
+        // Copies are paired by position, so renames follow the order
+        let renamed = diff(&old, &channels(&[("B", "News", "http://h/dup"), ("C", "News", "http://h/dup"), ("C", "News", "http://h/dup")]));
+        assert_eq!(changes(&renamed.renamed), vec![("A", "B"), ("B", "C")]);
+        assert!(renamed.added.is_empty());
+        assert!(renamed.removed.is_empty());
+    }
+
+    #[test]
+    fn removed_keeps_the_old_order_among_duplicates() {
+        let old = channels(&[("A", "News", "http://h/1"), ("B", "News", "http://h/dup"), ("C", "News", "http://h/2"), ("D", "News", "http://h/dup")]);
+        let new = channels(&[("B", "News", "http://h/dup")]);
+
+        let diff = diff(&old, &new);
+        assert_eq!(names(&diff.removed), vec!["A", "C", "D"]);
+        assert!(diff.added.is_empty());
+    }
+}
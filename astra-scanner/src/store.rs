@@ -0,0 +1,360 @@
+use std::collections::hash_map::{Entry as HashEntry, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Local};
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use serde::{Deserialize, Serialize};
+
+use crate::channel::Channel;
+use crate::config::ProbeMethod;
+use crate::detect::{Observation, Probe};
+use crate::http::ResponseMeta;
+use crate::scanner::Server;
+use crate::tls::CertificateInfo;
+
+/// File holding one `ip:port` line per server ever found
+pub const FOUND_SERVERS_PATH: &str = "found_servers.txt";
+
+/// File holding one JSON `Server` record per line
+pub const SERVER_RECORDS_PATH: &str = "servers.jsonl";
+
+/// File holding one JSON `Channel` record per line
+pub const CHANNEL_RECORDS_PATH: &str = "channels/channels.jsonl";
+
+/// File holding one JSON `RawResponse` per probe answered
+pub const RAW_RESPONSES_PATH: &str = "raw_responses.jsonl";
+
+/// Previous raw response log, kept after a rotation
+pub const RAW_RESPONSES_ROTATED_PATH: &str = "raw_responses.1.jsonl";
+
+/// Size at which the raw response log is rotated, so the two files together
+/// stay under twice this
+pub const RAW_RESPONSES_MAX_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Most body bytes kept in a raw response
+pub const RAW_BODY_BYTES: usize = 8 * 1024;
+
+/// Concurrent set of found servers keyed by socket address.
+///
+/// The map is sharded, so probes never serialize on a single lock, and it is
+/// only written on positive hits. It is seeded from `found_servers.txt`, which
+/// keeps servers from earlier runs from being saved and processed again.
+pub struct FoundServers {
+    // Value is `true` for servers first found during the current run
+    servers: DashMap<SocketAddr, bool>,
+}
+
+impl FoundServers {
+    pub fn new() -> Self {
+        Self {
+            servers: DashMap::new(),
+        }
+    }
+
+    /// Load the servers recorded by previous runs
+    pub fn load() -> Self {
+        let found = Self::new();
+
+        if let Ok(file) = File::open(FOUND_SERVERS_PATH) {
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                if let Ok(addr) = line.trim().parse::<SocketAddr>() {
+                    found.servers.insert(addr, false);
+                }
+            }
+        }
+
+        found
+    }
+
+    /// Record a positive hit, appending it to the results file if it is new.
+    ///
+    /// Returns `false` when the server was already known.
+    pub fn record(&self, addr: SocketAddr) -> Result<bool> {
+        let is_new = match self.servers.entry(addr) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(true);
+                true
+            }
+        };
+
+        // The entry, and with it the shard lock, is released before the file is touched
+        if is_new {
+            append_found_server(addr)?;
+        }
+        Ok(is_new)
+    }
+
+    /// Servers first found during the current run
+    pub fn found_this_run(&self) -> Vec<SocketAddr> {
+        self.servers.iter()
+            .filter(|entry| *entry.value())
+            .map(|entry| *entry.key())
+            .collect()
+    }
+}
+
+impl Default for FoundServers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Append a full server record, with its detection and version details.
+///
+/// Appending a server again updates its record.
+pub fn append_server_record(server: &Server) -> Result<()> {
+    append_lines(Path::new(SERVER_RECORDS_PATH), &format!("{}\n", serde_json::to_string(server)?))
+}
+
+/// Server records saved by previous runs, oldest first, in their latest
+/// version.
+///
+/// Lines that no longer parse are skipped.
+pub fn load_server_records() -> Vec<Server> {
+    let file = match File::open(SERVER_RECORDS_PATH) {
+        Ok(file) => file,
+        Err(_) => return Vec::new(),
+    };
+
+    // A server is appended again when its record changes; the last one wins
+    let mut servers: Vec<Server> = Vec::new();
+    let mut positions: HashMap<SocketAddr, usize> = HashMap::new();
+    for server in BufReader::new(file).lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str::<Server>(&line).ok())
+    {
+        match positions.entry(server.addr()) {
+            HashEntry::Occupied(entry) => servers[*entry.get()] = server,
+            HashEntry::Vacant(entry) => {
+                entry.insert(servers.len());
+                servers.push(server);
+            }
+        }
+    }
+    servers
+}
+
+/// Replace the server records, e.g. after re-fingerprinting.
+///
+/// The file is written next to the old one and renamed over it, so a crash
+/// never leaves it half written.
+pub fn rewrite_server_records(servers: &[Server]) -> Result<()> {
+    let tmp_path = format!("{}.tmp", SERVER_RECORDS_PATH);
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    for server in servers {
+        writeln!(writer, "{}", serde_json::to_string(server)?)?;
+    }
+    writer.into_inner().map_err(|e| anyhow!("Failed to write {}: {}", tmp_path, e))?.sync_all()?;
+
+    fs::rename(&tmp_path, SERVER_RECORDS_PATH)?;
+    Ok(())
+}
+
+/// Append channel records; a channel appended again is updated
+pub fn append_channel_records(channels: &[Channel]) -> Result<()> {
+    fs::create_dir_all(Path::new(CHANNEL_RECORDS_PATH).parent().unwrap_or(Path::new(".")))?;
+
+    let mut lines = String::new();
+    for channel in channels {
+        lines.push_str(&serde_json::to_string(channel)?);
+        lines.push('\n');
+    }
+    append_lines(Path::new(CHANNEL_RECORDS_PATH), &lines)
+}
+
+/// Append whole lines to `path` in a single write.
+///
+/// Files opened for appending take each write whole, so lines from
+/// concurrent appenders never interleave.
+pub fn append_lines(path: &Path, lines: &str) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+
+    file.write_all(lines.as_bytes())?;
+    Ok(())
+}
+
+/// Channel records saved by previous runs, one per URL, oldest first.
+///
+/// Later records of a channel update it but keep its first-seen time and,
+/// if they were not checked, its last verification.
+pub fn load_channel_records() -> Vec<Channel> {
+    let file = match File::open(CHANNEL_RECORDS_PATH) {
+        Ok(file) => file,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut channels: Vec<Channel> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for mut channel in BufReader::new(file).lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str::<Channel>(&line).ok())
+    {
+        match positions.entry(channel.url.clone()) {
+            HashEntry::Occupied(entry) => {
+                let existing = &mut channels[*entry.get()];
+                channel.first_seen = channel.first_seen.min(existing.first_seen);
+                if channel.verification.is_none() {
+                    channel.verification = existing.verification.take();
+                }
+                *existing = channel;
+            }
+            HashEntry::Vacant(entry) => {
+                entry.insert(channels.len());
+                channels.push(channel);
+            }
+        }
+    }
+    channels
+}
+
+/// A probe response as received, kept for offline re-fingerprinting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawResponse {
+    pub addr: SocketAddr,
+    pub captured_at: DateTime<Local>,
+    /// Request that was sent
+    pub method: ProbeMethod,
+    pub path: String,
+    pub base_url: String,
+    pub status: u16,
+    pub meta: ResponseMeta,
+    /// Start of the body, at most `RAW_BODY_BYTES`, base64 encoded
+    pub body: String,
+    pub certificate: Option<CertificateInfo>,
+}
+
+impl RawResponse {
+    /// Record of what `addr` answered to `probe`
+    pub fn new(addr: SocketAddr, probe: &Probe, observation: &Observation) -> Self {
+        let body = &observation.body[..observation.body.len().min(RAW_BODY_BYTES)];
+
+        Self {
+            addr,
+            captured_at: Local::now(),
+            method: probe.method,
+            path: probe.path.clone(),
+            base_url: observation.base_url.clone(),
+            status: observation.status.as_u16(),
+            meta: observation.meta.clone(),
+            body: STANDARD.encode(body),
+            certificate: observation.certificate.clone(),
+        }
+    }
+
+    /// The probe this is the answer to
+    pub fn probe(&self) -> Probe {
+        Probe::new(self.method, &self.path)
+    }
+
+    /// Decoded body sample; empty if the stored encoding is damaged
+    pub fn body_bytes(&self) -> Vec<u8> {
+        STANDARD.decode(&self.body).unwrap_or_default()
+    }
+}
+
+/// Append-only log of raw probe responses, shared by all scan tasks.
+///
+/// Lines are handed to a dedicated thread that owns a buffered writer, so
+/// scan tasks never block on the file. Once the log passes
+/// `RAW_RESPONSES_MAX_BYTES` it is rotated to `RAW_RESPONSES_ROTATED_PATH`,
+/// replacing the previous rotation. Dropping the log flushes what is queued.
+pub struct RawResponseLog {
+    sender: Option<mpsc::Sender<String>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl RawResponseLog {
+    pub fn open() -> Result<Self> {
+        let file = open_raw_responses()?;
+        let size = file.metadata()?.len();
+        let (sender, receiver) = mpsc::channel::<String>();
+
+        let writer = std::thread::Builder::new().name("raw-response-log".to_string()).spawn(move || {
+            let mut writer = BufWriter::new(file);
+            let mut size = size;
+            while let Ok(line) = receiver.recv() {
+                // Write everything queued, then flush so a crash loses little
+                let mut next = Some(line);
+                while let Some(line) = next {
+                    if let Err(e) = write_raw_line(&mut writer, &mut size, &line) {
+                        eprintln!("Failed to save raw response: {}", e);
+                    }
+                    next = receiver.try_recv().ok();
+                }
+                if let Err(e) = writer.flush() {
+                    eprintln!("Failed to save raw responses: {}", e);
+                }
+            }
+        })?;
+
+        Ok(Self {
+            sender: Some(sender),
+            writer: Some(writer),
+        })
+    }
+
+    /// Queue a response for the writer thread
+    pub fn append(&self, response: &RawResponse) -> Result<()> {
+        let line = serde_json::to_string(response)?;
+        self.sender.as_ref()
+            .and_then(|sender| sender.send(line).ok())
+            .ok_or_else(|| anyhow!("Raw response log writer stopped"))
+    }
+}
+
+impl Drop for RawResponseLog {
+    fn drop(&mut self) {
+        // Closing the channel lets the writer drain the queue and exit
+        self.sender.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+fn open_raw_responses() -> Result<File> {
+    Ok(OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(Path::new(RAW_RESPONSES_PATH))?)
+}
+
+/// Write one line to the log, rotating it first if it is full
+fn write_raw_line(writer: &mut BufWriter<File>, size: &mut u64, line: &str) -> Result<()> {
+    if *size > 0 && *size + line.len() as u64 + 1 > RAW_RESPONSES_MAX_BYTES {
+        writer.flush()?;
+        fs::rename(RAW_RESPONSES_PATH, RAW_RESPONSES_ROTATED_PATH)?;
+        *writer = BufWriter::new(open_raw_responses()?);
+        *size = 0;
+    }
+    writeln!(writer, "{}", line)?;
+    *size += line.len() as u64 + 1;
+    Ok(())
+}
+
+/// Raw responses saved by previous scans, oldest first, including the
+/// rotated log
+pub fn load_raw_responses() -> Vec<RawResponse> {
+    [RAW_RESPONSES_ROTATED_PATH, RAW_RESPONSES_PATH].iter()
+        .filter_map(|path| File::open(path).ok())
+        .flat_map(|file| BufReader::new(file).lines().map_while(Result::ok))
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect()
+}
+
+/// Append a server to the results file
+fn append_found_server(addr: SocketAddr) -> Result<()> {
+    append_lines(Path::new(FOUND_SERVERS_PATH), &format!("{}\n", addr))
+}
@@ -0,0 +1,76 @@
+use std::net::IpAddr;
+use chrono::{DateTime, TimeZone, Utc};
+use reqwest::Response;
+use serde::{Deserialize, Serialize};
+use x509_parser::prelude::{FromDer, GeneralName, X509Certificate};
+
+use crate::utils::sha256_hex;
+
+/// Certificate presented by a TLS server.
+///
+/// Self-signed and otherwise untrusted certificates are accepted during the
+/// probe, so this is recorded for identification only and says nothing about
+/// the server being trustworthy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    pub sans: Vec<String>,
+    pub not_before: Option<DateTime<Utc>>,
+    pub not_after: Option<DateTime<Utc>>,
+    /// SHA-256 of the DER encoding, lowercase hex
+    pub sha256: String,
+}
+
+impl CertificateInfo {
+    /// Parse a DER encoded leaf certificate
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = X509Certificate::from_der(der).ok()?;
+        let validity = cert.validity();
+
+        let sans = match cert.subject_alternative_name() {
+            Ok(Some(ext)) => ext.value.general_names.iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(dns) => Some(dns.to_string()),
+                    GeneralName::IPAddress(bytes) => ip_from_bytes(bytes).map(|ip| ip.to_string()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Some(Self {
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            sans,
+            not_before: Utc.timestamp_opt(validity.not_before.timestamp(), 0).single(),
+            not_after: Utc.timestamp_opt(validity.not_after.timestamp(), 0).single(),
+            sha256: sha256_hex(der),
+        })
+    }
+
+    /// Certificate of the peer that sent `response`, if it was served over TLS.
+    ///
+    /// Requires a client built with `tls_info(true)`.
+    pub fn from_response(response: &Response) -> Option<Self> {
+        response.extensions()
+            .get::<reqwest::tls::TlsInfo>()
+            .and_then(|info| info.peer_certificate())
+            .and_then(Self::from_der)
+    }
+
+    /// Whether the certificate is outside its validity window
+    pub fn is_expired(&self) -> bool {
+        let now = Utc::now();
+        self.not_after.is_some_and(|not_after| now > not_after)
+            || self.not_before.is_some_and(|not_before| now < not_before)
+    }
+}
+
+fn ip_from_bytes(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => <[u8; 4]>::try_from(bytes).ok().map(IpAddr::from),
+        16 => <[u8; 16]>::try_from(bytes).ok().map(IpAddr::from),
+        _ => None,
+    }
+}
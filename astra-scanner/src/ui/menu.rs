@@ -30,7 +30,7 @@ pub async fn main_menu(lang: &LanguageManager, config: &Config) -> Result<()> {
                 }
             },
             "2" => {
-                let mut astra_scanner = AstraScanner::new(lang, config);
+                let mut astra_scanner = AstraScanner::new(lang, config)?;
                 astra_scanner.scan().await?;
             },
             "3" => {
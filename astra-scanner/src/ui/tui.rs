@@ -16,9 +16,10 @@ use tui::{
     Frame, Terminal,
 };
 
-use crate::scanner::AstraScanner;
+use crate::scanner::Server;
 use crate::config::Config;
 use crate::lang::LanguageManager;
+use crate::store;
 
 /// Estructura principal para la TUI
 pub struct Tui<'a> {
@@ -27,6 +28,8 @@ pub struct Tui<'a> {
     active_tab: usize,
     scan_running: bool,
     scan_results: Option<ScanResults>,
+    /// Servidores guardados en servers.jsonl
+    servers: Vec<Server>,
 }
 
 /// Resultados del escaneo
@@ -45,6 +48,7 @@ impl<'a> Tui<'a> {
             active_tab: 0,
             scan_running: false,
             scan_results: None,
+            servers: store::load_server_records(),
         }
     }
     
@@ -114,6 +118,7 @@ impl<'a> Tui<'a> {
     
     async fn start_scan(&mut self) {
         self.scan_running = true;
+        self.servers = store::load_server_records();
         self.scan_results = Some(ScanResults {
             servers_found: 0,
             channels_found: 0,
@@ -248,12 +253,27 @@ impl<'a> Tui<'a> {
             f.render_widget(Paragraph::new(checked_text), stats_inner[2]);
         }
         
-        // Lista de servidores encontrados
+        // Lista de servidores encontrados, los más recientes primero
         let servers_block = Block::default()
             .borders(Borders::ALL)
             .title("Últimos servidores encontrados");
             
-        f.render_widget(servers_block, chunks[2]);
+        let items: Vec<ListItem> = self.servers.iter().rev()
+            .map(|server| {
+                ListItem::new(Spans::from(vec![
+                    Span::styled(format!("{:<22}", server.addr()), Style::default().fg(Color::Green)),
+                    Span::raw(format!("{:<6} ", server.service)),
+                    Span::styled(server.version_label(), Style::default().fg(Color::Cyan)),
+                    Span::styled(
+                        if server.confirmed { "" } else { " (sin confirmar)" },
+                        Style::default().fg(Color::Red),
+                    ),
+                    Span::raw(format!("  {}", server.discovery_time.format("%Y-%m-%d %H:%M"))),
+                ]))
+            })
+            .collect();
+            
+        f.render_widget(List::new(items).block(servers_block), chunks[2]);
     }
     
     fn draw_scanner<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
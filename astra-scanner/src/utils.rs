@@ -6,6 +6,7 @@ use anyhow::Result;
 use indicatif::{ProgressBar, ProgressStyle};
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use sha2::{Digest, Sha256};
 
 /// Validates if a string is a valid IPv4 address
 #[allow(dead_code)]
@@ -53,6 +54,53 @@ pub fn format_duration(seconds: u64) -> String {
     }
 }
 
+/// Lowercase hex SHA-256 digest of `data`
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// MurmurHash3 (x86, 32-bit, seed 0) of `data` as a signed integer,
+/// matching Python's `mmh3.hash` used by internet-scan datasets
+pub fn mmh3_32(data: &[u8]) -> i32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    let mut hash: u32 = 0;
+    let mut blocks = data.chunks_exact(4);
+
+    for block in &mut blocks {
+        let mut k = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        hash ^= k;
+        hash = hash
+            .rotate_left(13)
+            .wrapping_mul(5)
+            .wrapping_add(0xe654_6b64);
+    }
+
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        let mut k: u32 = 0;
+        for (i, byte) in tail.iter().enumerate() {
+            k |= (*byte as u32) << (8 * i);
+        }
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        hash ^= k;
+    }
+
+    hash ^= data.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^= hash >> 16;
+
+    hash as i32
+}
+
 /// Validates if a string is a valid CIDR notation
 #[allow(dead_code)]
 pub fn is_valid_cidr(cidr: &str) -> bool {
@@ -167,4 +215,30 @@ impl RateLimiter {
             *last = Instant::now();
         }
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Values of Python's `mmh3.hash`
+    #[test]
+    fn mmh3_matches_python() {
+        assert_eq!(mmh3_32(b""), 0);
+        assert_eq!(mmh3_32(b"hello"), 613153351);
+        assert_eq!(mmh3_32(b"foo"), -156908512);
+        assert_eq!(mmh3_32(b"Hello, world!"), -1070186941);
+        assert_eq!(
+            mmh3_32(b"The quick brown fox jumps over the lazy dog"),
+            776992547
+        );
+    }
+
+    #[test]
+    fn mmh3_tail_lengths() {
+        assert_eq!(mmh3_32(b"a"), 1009084850);
+        assert_eq!(mmh3_32(b"ab"), -1681926305);
+        assert_eq!(mmh3_32(b"abc"), -1277324294);
+        assert_eq!(mmh3_32(b"abcd"), 1139631978);
+    }
+}
@@ -0,0 +1,126 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+
+use crate::channel::{self, Channel};
+use crate::config::Config;
+use crate::export::{self, EXPORT_DIR};
+use crate::http;
+use crate::m3u::{self, Document, Extinf};
+
+/// `#EXTINF` attribute holding an entry's status in the annotated playlist
+const STATUS_ATTRIBUTE: &str = "x-astra-status";
+
+/// `#EXTINF` attribute holding when an entry was checked
+const CHECKED_AT_ATTRIBUTE: &str = "x-astra-checked-at";
+
+const REPORT_COLUMNS: &[&str] = &[
+    "line",
+    "name",
+    "url",
+    "scheme",
+    "group",
+    "tvg_id",
+    "status",
+    "checked_at",
+];
+
+/// Outcome of checking every entry of a playlist file
+#[derive(Debug, Clone)]
+pub struct FileVerification {
+    /// Copy of the playlist with each entry's status
+    pub playlist: PathBuf,
+    /// CSV report, a row per entry
+    pub report: PathBuf,
+    pub entries: usize,
+    pub working: usize,
+    pub failed: usize,
+    /// Entries whose URL scheme cannot be checked
+    pub unverified: usize,
+}
+
+/// Check the entries of a local playlist with the channel verifier, and
+/// write a copy annotated with each entry's status and a CSV report of it
+/// to the exports directory.
+pub async fn verify_file(path: &Path, config: &Config) -> Result<FileVerification> {
+    let document = m3u::parse_file(path).with_context(|| format!("Failed to read {:?}", path))?;
+    if document.entries.is_empty() {
+        bail!("No entries in {:?}", path);
+    }
+
+    let client = http::client_builder(config, Duration::from_secs(config.scanner.channel_timeout as u64))?.build()?;
+    let channels = document.entries.iter()
+        .map(|entry| Channel::from_entry(entry, entry.uri.trim(), None))
+        .collect();
+    let channels = channel::verify_channels(&client, channels, &config.scanner).await;
+
+    write(path, &document, &channels)
+}
+
+/// Status of a checked entry: `working`, `failed` or, for schemes the
+/// verifier cannot open, `unverified`
+pub fn status(channel: &Channel) -> &'static str {
+    match channel.is_working() {
+        Some(true) => "working",
+        Some(false) => "failed",
+        None => "unverified",
+    }
+}
+
+/// Write the annotated playlist and the report, named after the source file.
+/// `channels` are the document's entries, in order.
+fn write(source: &Path, document: &Document, channels: &[Channel]) -> Result<FileVerification> {
+    fs::create_dir_all(EXPORT_DIR)?;
+
+    let stem = source.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "playlist".to_string());
+    let timestamp = Local::now().format("%Y%m%d_%H%M%S");
+    let playlist_path = PathBuf::from(format!("{}/verified_{}_{}.m3u", EXPORT_DIR, stem, timestamp));
+    let report_path = playlist_path.with_extension("csv");
+
+    let mut annotated = document.clone();
+    for (entry, channel) in annotated.entries.iter_mut().zip(channels) {
+        let extinf = entry.extinf.get_or_insert_with(|| Extinf::untitled(&channel.name));
+        extinf.attributes.set(STATUS_ATTRIBUTE, status(channel));
+        if let Some(verification) = &channel.verification {
+            extinf.attributes.set(CHECKED_AT_ATTRIBUTE, verification.checked_at.to_rfc3339());
+        }
+    }
+    fs::write(&playlist_path, annotated.to_string())
+        .with_context(|| format!("Failed to write {:?}", playlist_path))?;
+
+    let file = File::create(&report_path)
+        .with_context(|| format!("Failed to create {:?}", report_path))?;
+    let mut writer = BufWriter::new(file);
+
+    export::write_csv_row(&mut writer, REPORT_COLUMNS.iter().map(|column| column.to_string()))?;
+    for (entry, channel) in document.entries.iter().zip(channels) {
+        export::write_csv_row(&mut writer, vec![
+            entry.line.to_string(),
+            channel.name.clone(),
+            channel.url.clone(),
+            channel.scheme().unwrap_or_default(),
+            channel.group.clone().unwrap_or_default(),
+            channel.tvg_id.clone().unwrap_or_default(),
+            status(channel).to_string(),
+            channel.verification.as_ref()
+                .map(|verification| verification.checked_at.to_rfc3339())
+                .unwrap_or_default(),
+        ])?;
+    }
+    writer.flush()?;
+
+    let count = |wanted: &str| channels.iter().filter(|channel| status(channel) == wanted).count();
+    Ok(FileVerification {
+        playlist: playlist_path,
+        report: report_path,
+        entries: channels.len(),
+        working: count("working"),
+        failed: count("failed"),
+        unverified: count("unverified"),
+    })
+}